@@ -18,6 +18,11 @@ pub struct BreakoutEngine {
     pub stick: bool,
     pub score: i32,
     pub elapsed_time: f32,
+    // (row, column) of every destroyed block, in the order they were destroyed
+    pub destroyed_blocks: Vec<(usize, usize)>,
+    // Times the ball bounced off the paddle, and how many of those were on its outer fifths
+    pub paddle_hits: u32,
+    pub edge_hits: u32,
 
     // Game constants
     pub blocks_w: usize,
@@ -68,6 +73,9 @@ impl BreakoutEngine {
             stick: true,
             score: 0,
             elapsed_time: 0.0,
+            destroyed_blocks: Vec::new(),
+            paddle_hits: 0,
+            edge_hits: 0,
             blocks_w: BLOCKS_W,
             blocks_h: BLOCKS_H,
             scr_w: SCR_W,
@@ -108,6 +116,19 @@ impl BreakoutEngine {
         ]
     }
 
+    /// Get the state including every block, for networks that can aim
+    /// Returns: [blocks row by row (1 if present), ball_x, ball_y, platform_x]
+    pub fn get_full_state(&self) -> Vec<f32> {
+        let mut state: Vec<f32> = self
+            .blocks
+            .iter()
+            .flat_map(|row| row.iter())
+            .map(|&b| if b { 1.0 } else { 0.0 })
+            .collect();
+        state.extend(self.get_state());
+        state
+    }
+
     // the bouncing of the ball should match the position where the ball collides
     pub fn bounce_ball(&mut self) {
         // 180   90    0
@@ -122,6 +143,11 @@ impl BreakoutEngine {
             + self.ball_min_shoot_angle;
         let angle_rad = PI * angle_deg / (180.);
 
+        self.paddle_hits += 1;
+        if !(0.2..=0.8).contains(&scale) {
+            self.edge_hits += 1;
+        }
+
         self.dx = self.ball_speed * f32::cos(angle_rad);
         self.dy = self.ball_speed * f32::sin(angle_rad);
         self.dy *= -1.0;
@@ -215,6 +241,7 @@ impl BreakoutEngine {
                     {
                         self.dy *= -1.0;
                         self.blocks[j][i] = false;
+                        self.destroyed_blocks.push((j, i));
                         self.score += 10;
                     }
                 }
@@ -1,5 +1,10 @@
 use std::sync::OnceLock;
 
+use crate::neat::activation::ActivationFunction;
+use crate::neat::aggregation::AggregationFunction;
+use crate::neat::hyperneat;
+use crate::neat::selection::{Objective, SelectionMethod};
+
 #[derive(Debug)]
 pub struct Config {
     pub init_mean: f32,
@@ -8,21 +13,99 @@ pub struct Config {
     pub max: f32,
     pub mutation_rate: f32,
     pub mutate_power: f32,
+    // Chance of replacing a bias with a new random value, weights use `random_weight_prob`
     pub replace_rate: f32,
     pub survival_threshold: f32,
 
+    // Parent selection
+    pub selection: SelectionMethod,
+    pub tournament_size: usize,
+
+    // Novelty search
+    pub objective: Objective,
+    // Neighbors a behavior's novelty is measured against
+    pub novelty_k: usize,
+    // Share of novelty in the blended objective, the rest is fitness
+    pub novelty_weight: f32,
+    pub novelty_archive_per_generation: usize,
+    pub novelty_archive_size: usize,
+
+    // MAP-Elites: cells along each behavior descriptor (paddle x, edge hits, blocks destroyed)
+    pub map_elites_resolution: Vec<usize>,
+    // Genomes evaluated per MAP-Elites iteration
+    pub map_elites_batch_size: usize,
+    // Chance an offspring is a crossover of two elites instead of a mutated copy of one
+    pub map_elites_crossover_prob: f32,
+
+    // Evolution strategies baseline: candidates per generation (in antithetic pairs)
+    pub es_population_size: usize,
+    // Standard deviation of the parameter noise
+    pub es_sigma: f32,
+    pub es_learning_rate: f32,
+    pub es_weight_decay: f32,
+    // Hidden neurons of the fixed topology (0 connects inputs straight to outputs)
+    pub es_hidden_neurons: i32,
+
+    // Deep Q-learning baseline
+    pub dqn_hidden_layers: Vec<usize>,
+    pub dqn_learning_rate: f32,
+    // Discount of future rewards
+    pub dqn_gamma: f32,
+    pub dqn_batch_size: usize,
+    pub dqn_replay_capacity: usize,
+    // Transitions collected before learning starts
+    pub dqn_warmup_steps: usize,
+    pub dqn_train_every: usize,
+    // Steps between copies of the online network into the target network
+    pub dqn_target_sync: usize,
+    pub dqn_epsilon_start: f32,
+    pub dqn_epsilon_end: f32,
+    pub dqn_epsilon_decay_steps: usize,
+    pub dqn_episodes_per_generation: usize,
+
+    // REINFORCE baseline, the value baseline has the same hidden layers as the policy
+    pub reinforce_hidden_layers: Vec<usize>,
+    pub reinforce_learning_rate: f32,
+    pub reinforce_baseline_learning_rate: f32,
+    pub reinforce_gamma: f32,
+    // Games played in parallel for every policy update
+    pub reinforce_episodes_per_generation: usize,
+
     pub num_inputs: i32,
     pub num_outputs: i32,
+    // Allow cycles and self links, evaluated with a recurrent network
+    pub allow_recurrent: bool,
 
+    // HyperNEAT: genomes are CPPNs that paint the weights of a substrate network
+    pub hyperneat: bool,
+    // Hidden nodes of the substrate, a grid of cols x rows (0 for no hidden layer)
+    pub substrate_hidden_cols: usize,
+    pub substrate_hidden_rows: usize,
+    pub substrate_activation: ActivationFunction,
+    // CPPN outputs below this magnitude don't express a link
+    pub substrate_weight_threshold: f32,
+    pub substrate_max_weight: f32,
 
     // Mutation probabilities
     pub add_node_prob: f32,
     pub add_link_prob: f32,
+    pub remove_node_prob: f32,
+    pub remove_link_prob: f32,
     pub enable_link_prob: f32,
     pub disable_link_prob: f32,
     pub shift_weight_prob: f32,
     pub random_weight_prob: f32,
 
+    // Activation functions
+    pub default_activation: ActivationFunction,
+    pub activation_mutate_prob: f32,
+    pub activation_options: Vec<ActivationFunction>,
+
+    // Aggregation functions
+    pub default_aggregation: AggregationFunction,
+    pub aggregation_mutate_prob: f32,
+    pub aggregation_options: Vec<AggregationFunction>,
+
     // Crossover coefficients
     pub c1_excess: f32,
     pub c2_disjoint: f32,
@@ -30,11 +113,23 @@ pub struct Config {
 
     // Compatiblity threshold for speciation
     pub compatibility_threshold: f32,
+    // Smallest number of offspring a species gets
+    pub min_species_size: usize,
+    // Species that don't improve for this many generations are removed
+    pub max_stagnation: usize,
+    // Number of best species protected from stagnation
+    pub species_elitism: usize,
+
+    // Elitism: best individuals copied unchanged into the next generation
+    pub elitism: usize,
+    pub elitism_per_species: usize,
 
     // General
     pub population_size: usize,
     pub num_generations: usize,
     pub num_steps: usize,
+    // Master seed of a training run, a random one is picked when not set
+    pub seed: Option<u64>,
 }
 
 impl Config {
@@ -50,25 +145,98 @@ impl Config {
             replace_rate: 0.05,
             survival_threshold: 0.2,
 
+            selection: SelectionMethod::Truncation,
+            tournament_size: 3,
+
+            objective: Objective::Fitness,
+            novelty_k: 15,
+            novelty_weight: 0.5,
+            novelty_archive_per_generation: 3,
+            novelty_archive_size: 1000,
+
+            map_elites_resolution: vec![10, 5, 10],
+            map_elites_batch_size: 150,
+            map_elites_crossover_prob: 0.2,
+
+            es_population_size: 100,
+            es_sigma: 0.1,
+            es_learning_rate: 0.03,
+            es_weight_decay: 0.005,
+            es_hidden_neurons: 0,
+
+            dqn_hidden_layers: vec![32, 32],
+            dqn_learning_rate: 5e-4,
+            dqn_gamma: 0.99,
+            dqn_batch_size: 32,
+            dqn_replay_capacity: 50_000,
+            dqn_warmup_steps: 1_000,
+            dqn_train_every: 4,
+            dqn_target_sync: 2_000,
+            dqn_epsilon_start: 1.0,
+            dqn_epsilon_end: 0.05,
+            dqn_epsilon_decay_steps: 100_000,
+            dqn_episodes_per_generation: 10,
+
+            reinforce_hidden_layers: vec![32, 32],
+            reinforce_learning_rate: 1e-3,
+            reinforce_baseline_learning_rate: 1e-3,
+            reinforce_gamma: 0.99,
+            reinforce_episodes_per_generation: 32,
+
             num_inputs: 3,
             num_outputs: 3,
+            allow_recurrent: false,
+
+            hyperneat: false,
+            substrate_hidden_cols: 5,
+            substrate_hidden_rows: 5,
+            substrate_activation: ActivationFunction::Tanh,
+            substrate_weight_threshold: 0.2,
+            substrate_max_weight: 3.0,
 
             add_node_prob: 0.03,
             add_link_prob: 0.05,
+            remove_node_prob: 0.01,
+            remove_link_prob: 0.02,
             enable_link_prob: 0.01,
             disable_link_prob: 0.01,
             shift_weight_prob: 0.8,  // standard weight mutation
             random_weight_prob: 0.1, // replace weight entirely
 
+            default_activation: ActivationFunction::Relu,
+            activation_mutate_prob: 0.01,
+            activation_options: ActivationFunction::ALL.to_vec(),
+
+            default_aggregation: AggregationFunction::Sum,
+            aggregation_mutate_prob: 0.01,
+            aggregation_options: AggregationFunction::ALL.to_vec(),
+
             c1_excess: 1.0,
             c2_disjoint: 1.0,
             c3_weight: 0.4,
 
             compatibility_threshold: 3.0,
+            min_species_size: 2,
+            max_stagnation: 15,
+            species_elitism: 2,
+
+            elitism: 1,
+            elitism_per_species: 1,
 
             population_size: 150,
             num_generations: 100,
             num_steps: 5000,
+            seed: None,
         })
     }
+
+    /// Number of inputs and outputs of the evolved genomes. In HyperNEAT mode these are CPPNs,
+    /// `num_inputs` and `num_outputs` then only describe the substrate.
+    pub fn genome_shape(&self) -> (i32, i32) {
+        if self.hyperneat {
+            (hyperneat::CPPN_INPUTS, hyperneat::CPPN_OUTPUTS)
+        } else {
+            (self.num_inputs, self.num_outputs)
+        }
+    }
 }
@@ -7,7 +7,9 @@ fn main() {
     println!("=== Testing Initial Genome ===\n");
     
     // Create a new genome
-    let genome = neat::Genome::new(3, 3);
+    let config = config::Config::global();
+    let mut rng = neat::rng::derive_rng(config.seed.unwrap_or(0), &[neat::rng::INIT]);
+    let genome = neat::Genome::new(1, config.num_inputs, config.num_outputs, &mut rng);
     
     println!("Genome structure:");
     println!("  Inputs: {}", genome.num_inputs);
@@ -72,7 +74,7 @@ fn main() {
         genome: genome.clone(),
         fitness: 0.0,
     };
-    let fitness = training::evaluate_individual(&individual);
+    let fitness = training::evaluate_individual(&individual, config.num_steps, &mut rng);
     println!("Fitness: {:.2}", fitness);
     println!("Expected frames alive: ~{:.0}", fitness);
 }
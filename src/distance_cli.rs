@@ -0,0 +1,178 @@
+mod config;
+mod neat;
+mod serialization;
+
+use neat::Genome;
+use neat::innovation::InnovationTracker;
+
+/// Give every link the innovation number of one shared tracker. Genomes saved by different
+/// runs numbered their links independently, so the same link can carry different numbers.
+fn renumber(genomes: &mut [Genome]) {
+    let innovations = InnovationTracker::new(genomes[0].num_inputs, genomes[0].num_outputs);
+    for genome in genomes.iter_mut() {
+        innovations.register_genome(genome);
+    }
+}
+
+/// Group genomes the way a fresh population is speciated: every genome joins the first
+/// cluster whose representative (its first member) is closer than `threshold`
+fn cluster(genomes: &[Genome], threshold: f32, config: &config::Config) -> Vec<Vec<usize>> {
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    for (i, genome) in genomes.iter().enumerate() {
+        if let Some(cluster) = clusters
+            .iter_mut()
+            .find(|c| genomes[c[0]].distance(genome, config) < threshold)
+        {
+            cluster.push(i);
+        } else {
+            clusters.push(vec![i]);
+        }
+    }
+    clusters
+}
+
+/// Value at fraction `q` of the sorted `values`
+fn quantile(values: &[f32], q: f32) -> f32 {
+    values[((values.len() - 1) as f32 * q).round() as usize]
+}
+
+fn main() {
+    let config = config::Config::global();
+    let mut threshold = config.compatibility_threshold;
+    let mut checkpoint: Option<String> = None;
+    let mut genome_files: Vec<String> = Vec::new();
+    let mut print_matrix = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--checkpoint" {
+            checkpoint = Some(args.next().expect("Parameter for checkpoint flag not given!"));
+        } else if arg == "--threshold" {
+            threshold = args
+                .next()
+                .expect("Parameter for threshold flag not given!")
+                .parse()
+                .expect("Invalid threshold number!");
+        } else if arg == "--matrix" {
+            print_matrix = true;
+        } else {
+            genome_files.push(arg);
+        }
+    }
+
+    let mut genomes: Vec<Genome> = if genome_files.is_empty() {
+        let filename = checkpoint.unwrap_or_else(|| "checkpoint.pb".to_string());
+        match serialization::load_population(&filename) {
+            Ok(population) => population.individuals.into_iter().map(|i| i.genome).collect(),
+            Err(e) => {
+                eprintln!("Failed to load checkpoint {}: {}", filename, e);
+                eprintln!("Usage: breakout-distance [--checkpoint <file>] [--threshold <x>] [--matrix] [genome.pb ...]");
+                return;
+            }
+        }
+    } else {
+        let mut genomes = Vec::new();
+        for filename in &genome_files {
+            match serialization::load_genome(filename) {
+                Ok(g) => genomes.push(g),
+                Err(e) => {
+                    eprintln!("Failed to load genome {}: {}", filename, e);
+                    return;
+                }
+            }
+        }
+        genomes
+    };
+
+    if genomes.len() < 2 {
+        eprintln!("Need at least two genomes to compare, got {}", genomes.len());
+        return;
+    }
+    if genomes.iter().any(|g| (g.num_inputs, g.num_outputs) != (genomes[0].num_inputs, genomes[0].num_outputs)) {
+        eprintln!("Genomes have different numbers of inputs or outputs, they can't be compared");
+        return;
+    }
+    // A checkpoint's genomes already share the population's innovation numbers
+    if !genome_files.is_empty() {
+        renumber(&mut genomes);
+    }
+    println!("Comparing {} genomes", genomes.len());
+
+    let n = genomes.len();
+    let mut matrix = vec![vec![0.0f32; n]; n];
+    let mut distances: Vec<f32> = Vec::new();
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let distance = genomes[i].distance(&genomes[j], config);
+            matrix[i][j] = distance;
+            matrix[j][i] = distance;
+            distances.push(distance);
+        }
+    }
+    distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    // Large populations make for an unreadable matrix, only print it when asked to
+    if print_matrix || n <= 20 {
+        println!("\n=== Distance Matrix ===");
+        print!("{:>8}", "");
+        for genome in &genomes {
+            print!("{:>8}", genome.id);
+        }
+        println!();
+        for (genome, row) in genomes.iter().zip(&matrix) {
+            print!("{:>8}", genome.id);
+            for distance in row {
+                print!("{:>8.3}", distance);
+            }
+            println!();
+        }
+    } else {
+        println!("(Pass --matrix to print the full {}x{} distance matrix)", n, n);
+    }
+
+    let mean = distances.iter().sum::<f32>() / distances.len() as f32;
+    println!("\n=== Pairwise Distances ===");
+    println!("  Pairs: {}", distances.len());
+    println!("  Min: {:.3}", distances[0]);
+    println!("  25%: {:.3}", quantile(&distances, 0.25));
+    println!("  Median: {:.3}", quantile(&distances, 0.5));
+    println!("  75%: {:.3}", quantile(&distances, 0.75));
+    println!("  Max: {:.3}", distances[distances.len() - 1]);
+    println!("  Mean: {:.3}", mean);
+
+    // The current threshold next to thresholds taken from the data
+    let mut thresholds = vec![threshold];
+    thresholds.extend([0.1, 0.25, 0.5, 0.75, 0.9].iter().map(|&q| quantile(&distances, q)));
+    thresholds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    thresholds.dedup();
+
+    println!("\n=== Clustering ===");
+    println!("  {:>10} {:>8} {:>8} {:>11}", "Threshold", "Species", "Largest", "Singletons");
+    for t in thresholds {
+        let clusters = cluster(&genomes, t, config);
+        let largest = clusters.iter().map(|c| c.len()).max().unwrap_or(0);
+        let singletons = clusters.iter().filter(|c| c.len() == 1).count();
+        let marker = if t == threshold { "*" } else { " " };
+        println!("{} {:>10.3} {:>8} {:>8} {:>11}", marker, t, clusters.len(), largest, singletons);
+    }
+    println!("  (* current compatibility_threshold)");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neat::test_utils::{genome, link};
+
+    #[test]
+    fn renumbered_genomes_match_by_link() {
+        let config = config::Config::global();
+        // The same network, numbered by two different runs
+        let a = genome(2, 1, &[1], vec![link(-1, 0, 0, 1.0, true), link(-2, 1, 7, 1.0, true), link(1, 0, 8, 1.0, true)]);
+        let b = genome(2, 1, &[1], vec![link(-1, 0, 0, 1.0, true), link(-2, 1, 12, 1.0, true), link(1, 0, 13, 1.0, true)]);
+        assert!(a.distance(&b, config) > 0.0);
+
+        let mut genomes = vec![a, b];
+        renumber(&mut genomes);
+        assert_eq!(genomes[0].distance(&genomes[1], config), 0.0);
+    }
+}
@@ -0,0 +1,171 @@
+mod breakout;
+mod config;
+mod neat;
+mod rl;
+mod serialization;
+mod training;
+
+use neat::{GenomeIdAllocator, Individual};
+use neat::rng::{self, derive_rng};
+use rayon::prelude::*;
+use rl::dqn::{Dqn, Transition};
+
+// Games the greedy policy plays after every generation, scored like NEAT individuals
+const EVAL_EPISODES: usize = 10;
+
+fn main() {
+    println!("Starting DQN training on Breakout...");
+
+    let config = config::Config::global();
+    let mut num_generations = config.num_generations;
+    let mut num_steps = config.num_steps;
+    let mut seed = config.seed;
+
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--num-gens" {
+            num_generations = args
+                .next()
+                .expect("Parameter for num-gen flag not given!")
+                .parse()
+                .expect("Invalid num-gen number!");
+            println!("num-gens flag loaded successfully, num_gens: {num_generations}");
+        }
+        if arg == "--num-steps" {
+            num_steps = args
+                .next()
+                .expect("Parameter for num-steps flag not given!")
+                .parse()
+                .expect("Invalid num-steps number!");
+            println!("num-steps flag loaded successfully, num_steps: {num_steps}");
+        }
+        if arg == "--seed" {
+            seed = Some(
+                args.next()
+                    .expect("Parameter for seed flag not given!")
+                    .parse()
+                    .expect("Invalid seed number!"),
+            );
+        }
+    }
+
+    if config.hyperneat {
+        eprintln!("DQN learns on the game state directly, turn off hyperneat in the config");
+        return;
+    }
+    assert_eq!(config.num_outputs as usize, rl::ACTIONS.len(), "one network output per action");
+
+    let seed = seed.unwrap_or_else(rand::random);
+    let mut sizes = vec![config.num_inputs as usize];
+    sizes.extend(&config.dqn_hidden_layers);
+    sizes.push(config.num_outputs as usize);
+    let mut dqn = Dqn::new(&sizes, &mut derive_rng(seed, &[rng::INIT]));
+    let mut rng = derive_rng(seed, &[rng::REPRODUCE]);
+
+    // Print the seed so any run can be reproduced with --seed
+    println!("Seed: {}", seed);
+    println!("Network: {:?}", sizes);
+
+    // Every snapshot of the network that gets scored or saved is a genome of its own
+    let genome_ids = GenomeIdAllocator::new(1);
+    let mut best = Individual { genome: dqn.online.to_genome(0), fitness: f32::NEG_INFINITY };
+    let mut best_generation = 0;
+    let overall_start = std::time::Instant::now();
+
+    for generation in 0..num_generations {
+        println!(
+            "\n=== Generation {}/{} ===",
+            generation + 1,
+            num_generations
+        );
+
+        let start_time = std::time::Instant::now();
+        let mut rewards: Vec<f32> = Vec::new();
+        let mut losses: Vec<f32> = Vec::new();
+        for _ in 0..config.dqn_episodes_per_generation {
+            let mut engine = training::new_episode(&mut rng);
+            let mut state = engine.get_state();
+            let mut total_reward = 0.0;
+            for _ in 0..num_steps {
+                let action = dqn.act(&state, &mut rng);
+                let reward = rl::step(&mut engine, action);
+                let next_state = engine.get_state();
+                total_reward += reward;
+
+                let transition = Transition {
+                    state,
+                    action,
+                    reward,
+                    next_state: next_state.clone(),
+                    done: engine.game_over,
+                };
+                losses.extend(dqn.observe(transition, &mut rng));
+                state = next_state;
+                if engine.game_over {
+                    break;
+                }
+            }
+            rewards.push(total_reward);
+        }
+        let duration = start_time.elapsed();
+
+        println!("  Training time: {:.2}s", duration.as_secs_f32());
+        println!("  Steps: {}", dqn.steps);
+        println!("  Epsilon: {:.3}", dqn.epsilon());
+        println!("  Avg reward: {:.2}", rewards.iter().sum::<f32>() / rewards.len() as f32);
+        if !losses.is_empty() {
+            println!("  Avg loss: {:.4}", losses.iter().sum::<f32>() / losses.len() as f32);
+        }
+
+        // Score the greedy policy on the same games NEAT is scored on
+        let policy = Individual { genome: dqn.online.to_genome(genome_ids.next_id()), fitness: 0.0 };
+        let fitness: Vec<f32> = (0..EVAL_EPISODES)
+            .into_par_iter()
+            .map(|i| {
+                let mut rng = derive_rng(seed, &[rng::EVALUATE, generation as u64, i as u64]);
+                training::evaluate_individual(&policy, num_steps, &mut rng)
+            })
+            .collect();
+        let avg_fitness = fitness.iter().sum::<f32>() / fitness.len() as f32;
+        println!("  Avg fitness: {:.2}", avg_fitness);
+        println!("  Min fitness: {:.2}", fitness.iter().copied().fold(f32::INFINITY, f32::min));
+        println!("  Max fitness: {:.2}", fitness.iter().copied().fold(f32::NEG_INFINITY, f32::max));
+
+        if avg_fitness > best.fitness {
+            best = Individual { fitness: avg_fitness, ..policy };
+            best_generation = generation + 1;
+        }
+        println!("  Best fitness: {:.2}", best.fitness);
+    }
+
+    let total_duration = overall_start.elapsed();
+
+    println!("\n=== Training Complete! ===");
+    println!("Total training time: {:.2}s", total_duration.as_secs_f32());
+    println!(
+        "Average time per generation: {:.2}s",
+        total_duration.as_secs_f32() / num_generations as f32
+    );
+    println!("Best policy: generation {} (genome {})", best_generation, best.genome.id);
+    println!("Best fitness: {:.2}", best.fitness);
+
+    // Save the best individual
+    if let Err(e) = serialization::save_individual(&best, "best_individual.pb") {
+        eprintln!("Failed to save best individual: {}", e);
+    }
+
+    // Also save just the genome
+    if let Err(e) = serialization::save_genome(&best.genome, "best_genome.pb") {
+        eprintln!("Failed to save best genome: {}", e);
+    }
+
+    // The final policy, whatever it scores
+    if let Err(e) = serialization::save_genome(&dqn.online.to_genome(genome_ids.next_id()), "dqn_genome.pb") {
+        eprintln!("Failed to save DQN genome: {}", e);
+    }
+
+    println!("\nResults saved to:");
+    println!("  - best_individual.pb");
+    println!("  - best_genome.pb");
+    println!("  - dqn_genome.pb");
+}
@@ -0,0 +1,225 @@
+//! Evolution strategies in the style of OpenAI ES (Salimans et al. 2017): the weights and biases
+//! of a fixed topology are one parameter vector, moved along a gradient estimated from Gaussian
+//! perturbations of it. A baseline for how much NEAT's topology search adds.
+
+use rand_distr::{Distribution, StandardNormal};
+use rayon::prelude::*;
+
+use crate::neat::activation::ActivationFunction;
+use crate::neat::aggregation::AggregationFunction;
+use crate::neat::innovation::InnovationTracker;
+use crate::neat::rng::{self, derive_rng};
+use crate::neat::{Genome, GenomeIdAllocator, Individual, LinkGene, LinkID, NeuronGene};
+use crate::training;
+
+/// Fully connected genome: inputs -> outputs, or inputs -> hidden -> outputs with `hidden`
+/// neurons. Innovation numbers match what NEAT would give the same links.
+pub fn layered_genome(id: i32, num_inputs: i32, num_outputs: i32, hidden: i32, seed: u64) -> Genome {
+    let mut rng = derive_rng(seed, &[rng::INIT]);
+    let mut genome = Genome::new(id, num_inputs, num_outputs, &mut rng);
+    if hidden == 0 {
+        return genome;
+    }
+
+    let innovations = InnovationTracker::new(num_inputs, num_outputs);
+    genome.links.clear();
+    let hidden_ids: Vec<i32> = (num_outputs..num_outputs + hidden).collect();
+    for &id in &hidden_ids {
+        genome.neurons.push(NeuronGene {
+            id,
+            bias: crate::neat::mutation::new_value(&mut rng),
+            activation: ActivationFunction::default_for(id, num_outputs),
+            aggregation: AggregationFunction::Sum,
+        });
+    }
+
+    let layers = [(genome.make_input_ids(), hidden_ids.clone()), (hidden_ids, genome.make_output_ids())];
+    for (sources, targets) in layers {
+        for &in_id in &sources {
+            for &out_id in &targets {
+                let id = LinkID { in_id, out_id };
+                genome.links.push(LinkGene {
+                    innovation: innovations.link_innovation(&id),
+                    id,
+                    weight: crate::neat::mutation::new_value(&mut rng),
+                    is_enabled: true,
+                });
+            }
+        }
+    }
+    genome
+}
+
+/// Link weights followed by the biases of every non-input neuron
+pub fn parameters(genome: &Genome) -> Vec<f32> {
+    genome
+        .links
+        .iter()
+        .map(|l| l.weight)
+        .chain(genome.neurons.iter().filter(|n| n.id >= 0).map(|n| n.bias))
+        .collect()
+}
+
+/// Inverse of `parameters`
+pub fn set_parameters(genome: &mut Genome, parameters: &[f32]) {
+    let mut values = parameters.iter();
+    for link in genome.links.iter_mut() {
+        link.weight = *values.next().unwrap();
+    }
+    for neuron in genome.neurons.iter_mut().filter(|n| n.id >= 0) {
+        neuron.bias = *values.next().unwrap();
+    }
+}
+
+/// Fitness shaping: replace every value by its rank, scaled to [-0.5, 0.5]. The update then
+/// only depends on the ordering of the candidates, not on the scale of the fitness.
+pub fn centered_ranks(values: &[f32]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap());
+    let mut ranks = vec![0.0; values.len()];
+    let scale = (values.len().max(2) - 1) as f32;
+    for (rank, &i) in order.iter().enumerate() {
+        ranks[i] = rank as f32 / scale - 0.5;
+    }
+    ranks
+}
+
+pub struct EvolutionStrategy {
+    // Topology, and the current parameters once `genome()` is called
+    pub template: Genome,
+    pub parameters: Vec<f32>,
+    // Every candidate and snapshot of the parameters is a genome with its own ID
+    pub genome_ids: GenomeIdAllocator,
+    // Adam moment estimates
+    m: Vec<f32>,
+    v: Vec<f32>,
+    pub generation: usize,
+    // Master seed, every random decision of the run is derived from it
+    pub seed: u64,
+}
+
+impl EvolutionStrategy {
+    pub fn new(template: Genome, seed: u64) -> Self {
+        let parameters = parameters(&template);
+        let genome_ids = GenomeIdAllocator::new(1);
+        genome_ids.skip_past(template.id);
+        Self {
+            template,
+            genome_ids,
+            m: vec![0.0; parameters.len()],
+            v: vec![0.0; parameters.len()],
+            parameters,
+            generation: 0,
+            seed,
+        }
+    }
+
+    /// The current parameters in the fixed topology
+    pub fn genome(&self) -> Genome {
+        let mut genome = Genome { id: self.genome_ids.next_id(), ..self.template.clone() };
+        set_parameters(&mut genome, &self.parameters);
+        genome
+    }
+
+    /// One noise vector per antithetic pair, each from its own RNG stream
+    pub fn noise(&self) -> Vec<Vec<f32>> {
+        let config = crate::config::Config::global();
+        (0..(config.es_population_size / 2).max(1))
+            .into_par_iter()
+            .map(|pair| {
+                let mut rng = derive_rng(self.seed, &[rng::REPRODUCE, self.generation as u64, pair as u64]);
+                self.parameters.iter().map(|_| StandardNormal.sample(&mut rng)).collect()
+            })
+            .collect()
+    }
+
+    /// The candidates to evaluate, parameters + sigma * noise and - sigma * noise for every pair
+    pub fn candidates(&self, noise: &[Vec<f32>]) -> Vec<Individual> {
+        let sigma = crate::config::Config::global().es_sigma;
+        noise
+            .iter()
+            .flat_map(|epsilon| [sigma, -sigma].map(|sign| (epsilon, sign)))
+            .map(|(epsilon, scale)| {
+                let perturbed: Vec<f32> = self.parameters.iter().zip(epsilon).map(|(p, e)| p + scale * e).collect();
+                let mut genome = Genome { id: self.genome_ids.next_id(), ..self.template.clone() };
+                set_parameters(&mut genome, &perturbed);
+                Individual { genome, fitness: 0.0 }
+            })
+            .collect()
+    }
+
+    /// Play every candidate with `training::evaluate_individual`. Both candidates of a pair
+    /// see the same game, so their difference comes from the noise alone.
+    pub fn evaluate(&self, candidates: &mut [Individual], num_steps: usize) {
+        candidates.par_iter_mut().enumerate().for_each(|(index, candidate)| {
+            let mut rng = derive_rng(self.seed, &[rng::EVALUATE, self.generation as u64, (index / 2) as u64]);
+            candidate.fitness = training::evaluate_individual(candidate, num_steps, &mut rng);
+        });
+    }
+
+    /// Take an Adam step along the estimated gradient. `fitness` holds the fitness of the
+    /// candidates in the order `candidates` returned them.
+    pub fn update(&mut self, noise: &[Vec<f32>], fitness: &[f32]) {
+        const BETA1: f32 = 0.9;
+        const BETA2: f32 = 0.999;
+        const EPSILON: f32 = 1e-8;
+        let config = crate::config::Config::global();
+
+        let ranks = centered_ranks(fitness);
+        let mut gradient = vec![0.0; self.parameters.len()];
+        for (epsilon, pair) in noise.iter().zip(ranks.chunks(2)) {
+            let weight = pair[0] - pair[1];
+            for (g, e) in gradient.iter_mut().zip(epsilon) {
+                *g += weight * e;
+            }
+        }
+
+        self.generation += 1;
+        let t = self.generation as i32;
+        for (i, g) in gradient.iter_mut().enumerate() {
+            *g = *g / (fitness.len() as f32 * config.es_sigma) - config.es_weight_decay * self.parameters[i];
+            self.m[i] = BETA1 * self.m[i] + (1.0 - BETA1) * *g;
+            self.v[i] = BETA2 * self.v[i] + (1.0 - BETA2) * *g * *g;
+            let m_hat = self.m[i] / (1.0 - BETA1.powi(t));
+            let v_hat = self.v[i] / (1.0 - BETA2.powi(t));
+            self.parameters[i] += config.es_learning_rate * m_hat / (v_hat.sqrt() + EPSILON);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn climbs_towards_the_fitness_peak() {
+        assert_eq!(centered_ranks(&[3.0, 1.0, 2.0]), vec![0.5, -0.5, 0.0]);
+
+        let genome = layered_genome(1, 2, 1, 2, 11);
+        assert_eq!(genome.links.len(), 2 * 2 + 2);
+        assert_eq!(parameters(&genome).len(), 6 + 3);
+
+        // Fitness peaks with every parameter at 1
+        let distance = |parameters: &[f32]| parameters.iter().map(|p| (p - 1.0) * (p - 1.0)).sum::<f32>();
+        let mut es = EvolutionStrategy::new(genome, 11);
+        let start = distance(&es.parameters);
+        for _ in 0..300 {
+            let noise = es.noise();
+            let fitness: Vec<f32> = es
+                .candidates(&noise)
+                .iter()
+                .map(|c| -distance(&parameters(&c.genome)))
+                .collect();
+            es.update(&noise, &fitness);
+        }
+        assert!(distance(&es.parameters) < start * 0.1);
+
+        // Candidates never share an ID with the template or each other
+        let mut ids: Vec<i32> = es.candidates(&es.noise()).iter().map(|c| c.genome.id).collect();
+        ids.push(1);
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 2 * (crate::config::Config::global().es_population_size / 2).max(1) + 1);
+        assert_eq!(parameters(&es.genome()), es.parameters);
+    }
+}
@@ -0,0 +1,136 @@
+mod breakout;
+mod config;
+mod es;
+mod neat;
+mod serialization;
+mod training;
+
+use es::EvolutionStrategy;
+use neat::Individual;
+
+fn main() {
+    println!("Starting evolution strategies training on Breakout...");
+    println!(
+        "Using parallel processing with {} CPU cores",
+        rayon::current_num_threads()
+    );
+
+    let config = config::Config::global();
+    let mut num_generations = config.num_generations;
+    let mut num_steps = config.num_steps;
+    let mut seed = config.seed;
+    let mut hidden = config.es_hidden_neurons;
+
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--num-gens" {
+            num_generations = args
+                .next()
+                .expect("Parameter for num-gen flag not given!")
+                .parse()
+                .expect("Invalid num-gen number!");
+            println!("num-gens flag loaded successfully, num_gens: {num_generations}");
+        }
+        if arg == "--num-steps" {
+            num_steps = args
+                .next()
+                .expect("Parameter for num-steps flag not given!")
+                .parse()
+                .expect("Invalid num-steps number!");
+            println!("num-steps flag loaded successfully, num_steps: {num_steps}");
+        }
+        if arg == "--hidden" {
+            hidden = args
+                .next()
+                .expect("Parameter for hidden flag not given!")
+                .parse()
+                .expect("Invalid number of hidden neurons!");
+            println!("hidden flag loaded successfully, hidden: {hidden}");
+        }
+        if arg == "--seed" {
+            seed = Some(
+                args.next()
+                    .expect("Parameter for seed flag not given!")
+                    .parse()
+                    .expect("Invalid seed number!"),
+            );
+        }
+    }
+
+    let seed = seed.unwrap_or_else(rand::random);
+    let (num_inputs, num_outputs) = config.genome_shape();
+    let mut es = EvolutionStrategy::new(es::layered_genome(1, num_inputs, num_outputs, hidden, seed), seed);
+
+    // Print the seed so any run can be reproduced with --seed
+    println!("Seed: {}", seed);
+    println!("Parameters: {}", es.parameters.len());
+
+    let mut best = Individual { genome: es.genome(), fitness: f32::NEG_INFINITY };
+    let overall_start = std::time::Instant::now();
+
+    for generation in 0..num_generations {
+        println!(
+            "\n=== Generation {}/{} ===",
+            generation + 1,
+            num_generations
+        );
+
+        let start_time = std::time::Instant::now();
+        let noise = es.noise();
+        let mut candidates = es.candidates(&noise);
+        es.evaluate(&mut candidates, num_steps);
+        let duration = start_time.elapsed();
+
+        let fitness: Vec<f32> = candidates.iter().map(|c| c.fitness).collect();
+        println!("  Evaluation time: {:.2}s", duration.as_secs_f32());
+        println!(
+            "  Evaluations/sec: {:.2}",
+            candidates.len() as f32 / duration.as_secs_f32()
+        );
+        println!("  Avg fitness: {:.2}", fitness.iter().sum::<f32>() / fitness.len() as f32);
+        println!("  Min fitness: {:.2}", fitness.iter().copied().fold(f32::INFINITY, f32::min));
+        println!("  Max fitness: {:.2}", fitness.iter().copied().fold(f32::NEG_INFINITY, f32::max));
+
+        // Keep the best candidate ever played, the parameters themselves are never evaluated
+        if let Some(candidate) = candidates
+            .iter()
+            .min_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap())
+            && candidate.fitness > best.fitness
+        {
+            best = candidate.clone();
+        }
+        println!("  Best fitness: {:.2}", best.fitness);
+
+        es.update(&noise, &fitness);
+    }
+
+    let total_duration = overall_start.elapsed();
+
+    println!("\n=== Training Complete! ===");
+    println!("Total training time: {:.2}s", total_duration.as_secs_f32());
+    println!(
+        "Average time per generation: {:.2}s",
+        total_duration.as_secs_f32() / num_generations as f32
+    );
+    println!("Best fitness: {:.2}", best.fitness);
+
+    // Save the best individual
+    if let Err(e) = serialization::save_individual(&best, "best_individual.pb") {
+        eprintln!("Failed to save best individual: {}", e);
+    }
+
+    // Also save just the genome
+    if let Err(e) = serialization::save_genome(&best.genome, "best_genome.pb") {
+        eprintln!("Failed to save best genome: {}", e);
+    }
+
+    // The final parameters, which aren't tuned to any one game
+    if let Err(e) = serialization::save_genome(&es.genome(), "es_genome.pb") {
+        eprintln!("Failed to save ES genome: {}", e);
+    }
+
+    println!("\nResults saved to:");
+    println!("  - best_individual.pb");
+    println!("  - best_genome.pb");
+    println!("  - es_genome.pb");
+}
@@ -0,0 +1,137 @@
+mod breakout;
+mod config;
+mod neat;
+mod serialization;
+mod training;
+
+use neat::map_elites::MapElites;
+
+fn main() {
+    println!("Starting MAP-Elites training on Breakout...");
+    println!(
+        "Using parallel processing with {} CPU cores",
+        rayon::current_num_threads()
+    );
+
+    let config = config::Config::global();
+    let mut num_generations = config.num_generations;
+    let mut num_steps = config.num_steps;
+    let mut seed = config.seed;
+
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--num-gens" {
+            num_generations = args
+                .next()
+                .expect("Parameter for num-gen flag not given!")
+                .parse()
+                .expect("Invalid num-gen number!");
+            println!("num-gens flag loaded successfully, num_gens: {num_generations}");
+        }
+        if arg == "--num-steps" {
+            num_steps = args
+                .next()
+                .expect("Parameter for num-steps flag not given!")
+                .parse()
+                .expect("Invalid num-steps number!");
+            println!("num-steps flag loaded successfully, num_steps: {num_steps}");
+        }
+        if arg == "--seed" {
+            seed = Some(
+                args.next()
+                    .expect("Parameter for seed flag not given!")
+                    .parse()
+                    .expect("Invalid seed number!"),
+            );
+        }
+    }
+
+    if config.map_elites_resolution.contains(&0) {
+        eprintln!("Every map_elites_resolution entry needs at least one cell, got {:?}", config.map_elites_resolution);
+        return;
+    }
+
+    let mut archive = if std::env::args().any(|x| &x == "--resume") {
+        match serialization::load_map_elites("map_elites.pb") {
+            Ok(a) => {
+                println!("Resuming from generation {} with {} elites", a.generation + 1, a.cells.len());
+                a
+            }
+            Err(e) => {
+                eprintln!("Failed to load archive: {}", e);
+                return;
+            }
+        }
+    } else {
+        MapElites::new(config.map_elites_resolution.clone(), seed.unwrap_or_else(rand::random))
+    };
+
+    // Print the seed so any run can be reproduced with --seed
+    println!("Seed: {}", archive.seed);
+
+    let overall_start = std::time::Instant::now();
+
+    for generation in 0..num_generations {
+        println!(
+            "\n=== Generation {}/{} ===",
+            generation + 1,
+            num_generations
+        );
+
+        let mut batch = archive.next_batch();
+        let stats = training::train_population_with_stats(&mut batch, num_steps, archive.seed, archive.generation);
+
+        println!("  Evaluation time: {:.2}s", stats.duration.as_secs_f32());
+        println!(
+            "  Evaluations/sec: {:.2}",
+            stats.population_size as f32 / stats.duration.as_secs_f32()
+        );
+        println!("  Avg fitness: {:.2}", stats.avg_fitness);
+        println!("  Max fitness: {:.2}", stats.max_fitness);
+
+        let descriptors = stats.episodes.into_iter().map(|e| e.descriptors).collect();
+        let inserted = archive.insert_batch(batch, descriptors);
+        println!("  New elites: {}", inserted);
+        println!("  Cells filled: {}/{}", archive.cells.len(), archive.num_cells());
+        println!("  QD score: {:.0}", archive.qd_score());
+        if let Some(best) = archive.best() {
+            println!("  Best genome ID: {}", best.individual.genome.id);
+            println!("  Best fitness: {:.2}", best.individual.fitness);
+        }
+
+        if let Err(e) = serialization::save_map_elites(&archive, "map_elites.pb") {
+            eprintln!("Failed to save archive: {}", e);
+        }
+    }
+
+    let total_duration = overall_start.elapsed();
+
+    println!("\n=== Training Complete! ===");
+    println!("Total training time: {:.2}s", total_duration.as_secs_f32());
+    println!(
+        "Average time per generation: {:.2}s",
+        total_duration.as_secs_f32() / num_generations as f32
+    );
+    println!("Cells filled: {}/{}", archive.cells.len(), archive.num_cells());
+
+    // The fittest elites, browse all of them in breakout-ai
+    println!("\nElites (paddle x, edge hits, blocks destroyed):");
+    let mut elites = archive.elites();
+    elites.sort_by(|a, b| b.individual.fitness.partial_cmp(&a.individual.fitness).unwrap());
+    for elite in elites.iter().take(10) {
+        println!(
+            "  Genome {}: fitness {:.0}, paddle x {:.2}, edge hits {:.2}, blocks {:.2}",
+            elite.individual.genome.id,
+            elite.individual.fitness,
+            elite.descriptors[0],
+            elite.descriptors[1],
+            elite.descriptors[2],
+        );
+    }
+    if elites.len() > 10 {
+        println!("  ... and {} more", elites.len() - 10);
+    }
+
+    println!("\nResults saved to:");
+    println!("  - map_elites.pb (continue with --resume, browse with breakout-ai --archive)");
+}
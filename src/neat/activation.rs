@@ -0,0 +1,55 @@
+use super::nn::relu;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivationFunction {
+    Sigmoid,
+    Tanh,
+    Relu,
+    Identity,
+    Gaussian,
+    Sin,
+    Abs,
+    Step,
+}
+
+impl ActivationFunction {
+    pub const ALL: [ActivationFunction; 8] = [
+        ActivationFunction::Sigmoid,
+        ActivationFunction::Tanh,
+        ActivationFunction::Relu,
+        ActivationFunction::Identity,
+        ActivationFunction::Gaussian,
+        ActivationFunction::Sin,
+        ActivationFunction::Abs,
+        ActivationFunction::Step,
+    ];
+
+    pub fn apply(&self, x: f32) -> f32 {
+        match self {
+            ActivationFunction::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            ActivationFunction::Tanh => x.tanh(),
+            ActivationFunction::Relu => relu(x),
+            ActivationFunction::Identity => x,
+            ActivationFunction::Gaussian => (-x * x).exp(),
+            ActivationFunction::Sin => x.sin(),
+            ActivationFunction::Abs => x.abs(),
+            ActivationFunction::Step => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// Activation a neuron starts with. Outputs stay linear so they can express negative values
+    /// for comparison, hidden neurons use the configured default.
+    pub fn default_for(id: i32, num_outputs: i32) -> ActivationFunction {
+        if id >= 0 && id < num_outputs {
+            ActivationFunction::Identity
+        } else {
+            crate::config::Config::global().default_activation
+        }
+    }
+}
@@ -0,0 +1,40 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggregationFunction {
+    Sum,
+    Product,
+    Max,
+    Min,
+    Mean,
+}
+
+impl AggregationFunction {
+    pub const ALL: [AggregationFunction; 5] = [
+        AggregationFunction::Sum,
+        AggregationFunction::Product,
+        AggregationFunction::Max,
+        AggregationFunction::Min,
+        AggregationFunction::Mean,
+    ];
+
+    /// Combine a neuron's weighted inputs. A neuron without inputs aggregates to 0.
+    pub fn aggregate(&self, weighted_inputs: impl Iterator<Item = f32>) -> f32 {
+        let mut count = 0;
+        let mut acc: Option<f32> = None;
+        for x in weighted_inputs {
+            count += 1;
+            acc = Some(match (self, acc) {
+                (_, None) => x,
+                (AggregationFunction::Sum | AggregationFunction::Mean, Some(a)) => a + x,
+                (AggregationFunction::Product, Some(a)) => a * x,
+                (AggregationFunction::Max, Some(a)) => a.max(x),
+                (AggregationFunction::Min, Some(a)) => a.min(x),
+            });
+        }
+
+        match (self, acc) {
+            (_, None) => 0.0,
+            (AggregationFunction::Mean, Some(a)) => a / count as f32,
+            (_, Some(a)) => a,
+        }
+    }
+}
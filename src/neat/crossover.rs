@@ -1,37 +1,67 @@
+use rand::Rng;
+
 use crate::neat::*;
-// TODO: maybe add activation function too?
-pub fn crossover_neuron(a: &NeuronGene, b: &NeuronGene) -> NeuronGene {
+use crate::neat::mutation::would_create_cycle;
+
+pub fn crossover_neuron(a: &NeuronGene, b: &NeuronGene, rng: &mut impl Rng) -> NeuronGene {
     assert_eq!(a.id, b.id);
 
-    let mut rng = rand::rng();
     let biases = [a.bias, b.bias];
-    let bias = biases.choose(&mut rng).unwrap();
+    let bias = biases.choose(rng).unwrap();
+    let activations = [a.activation, b.activation];
+    let activation = activations.choose(rng).unwrap();
+    let aggregations = [a.aggregation, b.aggregation];
+    let aggregation = aggregations.choose(rng).unwrap();
 
     NeuronGene {
         id: a.id,
         bias: *bias,
+        activation: *activation,
+        aggregation: *aggregation,
     }
 }
 
-pub fn crossover_link(a: &LinkGene, b: &LinkGene) -> LinkGene {
-    assert_eq!(a.id, b.id);
+pub fn crossover_link(a: &LinkGene, b: &LinkGene, rng: &mut impl Rng) -> LinkGene {
+    assert_eq!(a.innovation, b.innovation);
 
-    let mut rng = rand::rng();
     let weights = [a.weight, b.weight];
-    let weight = weights.choose(&mut rng).unwrap();
-    let enables = [a.is_enabled, b.is_enabled];
-    let enable = enables.choose(&mut rng).unwrap();
+    let weight = weights.choose(rng).unwrap();
 
     LinkGene {
         id: a.id.clone(),
         weight: *weight,
-        is_enabled: *enable,
+        // A matching gene is disabled if it is disabled in either parent
+        is_enabled: a.is_enabled && b.is_enabled,
+        innovation: a.innovation,
+    }
+}
+
+/// Inherit a neuron from `parent`, crossing it over with `other`'s neuron if both have it
+fn inherit_neuron(offspring: &mut Genome, id: i32, parent: &Genome, other: &Genome, rng: &mut impl Rng) {
+    if offspring.find_neuron(&id).is_some() {
+        return;
+    }
+
+    if let Some(neuron) = parent.find_neuron(&id) {
+        if let Some(other_neuron) = other.find_neuron(&id) {
+            offspring.neurons.push(crossover_neuron(neuron, other_neuron, rng));
+        } else {
+            offspring.neurons.push(neuron.clone());
+        }
     }
 }
 
-pub fn crossover(dominant: &Individual, recessive: &Individual) -> Genome {
+/// Crossover as described in the NEAT paper. Genes are aligned by innovation number:
+/// matching genes are inherited randomly from either parent, disjoint and excess genes come
+/// from the fitter parent. When both parents are equally fit, disjoint and excess genes of
+/// both parents are inherited. The offspring gets the genome ID `id`.
+pub fn crossover(a: &Individual, b: &Individual, id: i32, rng: &mut impl Rng) -> Genome {
+    let (dominant, recessive) = if b.fitness > a.fitness { (b, a) } else { (a, b) };
+    let equal_fitness = a.fitness == b.fitness;
+    let config = crate::config::Config::global();
+
     let mut offspring = Genome {
-        id: Genome::genome_indexer(),
+        id,
         num_inputs: dominant.genome.num_inputs,
         num_outputs: dominant.genome.num_outputs,
         neurons: Vec::new(),
@@ -39,22 +69,121 @@ pub fn crossover(dominant: &Individual, recessive: &Individual) -> Genome {
     };
 
     // inherit neuron genes
-    for dominant_neuron in dominant.genome.neurons.clone() {
-        if let Some(recessive_neuron) = recessive.genome.find_neuron(&dominant_neuron.id) {
-            offspring.neurons.push(crossover_neuron(&dominant_neuron, recessive_neuron));
+    for neuron in &dominant.genome.neurons {
+        inherit_neuron(&mut offspring, neuron.id, &dominant.genome, &recessive.genome, rng);
+    }
+
+    // inherit link genes
+    for dominant_link in &dominant.genome.links {
+        if let Some(recessive_link) = recessive.genome.find_link_by_innovation(dominant_link.innovation) {
+            offspring.links.push(crossover_link(dominant_link, recessive_link, rng));
         } else {
-            offspring.neurons.push(dominant_neuron);
+            offspring.links.push(dominant_link.clone());
         }
     }
 
-    // inherit link gene
-    for dominant_link in dominant.genome.links.clone() {
-        if let Some(recessive_link) = dominant.genome.find_link(&dominant_link.id) {
-            offspring.links.push(crossover_link(&dominant_link, recessive_link));
-        } else {
-            offspring.links.push(dominant_link);
+    if equal_fitness {
+        for recessive_link in &recessive.genome.links {
+            if offspring.find_link_by_innovation(recessive_link.innovation).is_some() {
+                continue;
+            }
+            // Mixing the structure of two parents could close a loop
+            if !config.allow_recurrent && would_create_cycle(&offspring.links, recessive_link.id.in_id, recessive_link.id.out_id) {
+                continue;
+            }
+
+            inherit_neuron(&mut offspring, recessive_link.id.in_id, &recessive.genome, &dominant.genome, rng);
+            inherit_neuron(&mut offspring, recessive_link.id.out_id, &recessive.genome, &dominant.genome, rng);
+            offspring.links.push(recessive_link.clone());
         }
     }
 
     offspring
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neat::rng::derive_rng;
+    use crate::neat::test_utils::{genome, individual, link};
+
+    fn innovations(genome: &Genome) -> Vec<i32> {
+        let mut innovations: Vec<i32> = genome.links.iter().map(|l| l.innovation).collect();
+        innovations.sort();
+        innovations
+    }
+
+    // Parent a: 0, 1, 3 (disjoint)     Parent b: 0, 1, 2 (disjoint), 4, 5 (excess)
+    fn parents(fitness_a: f32, fitness_b: f32) -> (Individual, Individual) {
+        let a = individual(
+            genome(2, 1, &[1], vec![
+                link(-1, 0, 0, 1.0, true),
+                link(-2, 0, 1, 1.0, true),
+                link(-1, 1, 3, 1.0, true),
+            ]),
+            fitness_a,
+        );
+        let b = individual(
+            genome(2, 1, &[2, 3], vec![
+                link(-1, 0, 0, 2.0, true),
+                link(-2, 0, 1, 2.0, true),
+                link(-2, 2, 2, 2.0, true),
+                link(2, 0, 4, 2.0, true),
+                link(-1, 3, 5, 2.0, true),
+            ]),
+            fitness_b,
+        );
+        (a, b)
+    }
+
+    #[test]
+    fn disjoint_and_excess_from_fitter_parent() {
+        let rng = &mut derive_rng(0, &[]);
+        let (a, b) = parents(1.0, 10.0);
+
+        // Argument order must not matter, the fitter parent is always dominant
+        for offspring in [crossover(&a, &b, 0, rng), crossover(&b, &a, 0, rng)] {
+            assert_eq!(innovations(&offspring), vec![0, 1, 2, 4, 5]);
+            let mut neuron_ids: Vec<i32> = offspring.neurons.iter().map(|n| n.id).collect();
+            neuron_ids.sort();
+            assert_eq!(neuron_ids, vec![-2, -1, 0, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn disjoint_and_excess_from_both_when_equal_fitness() {
+        let rng = &mut derive_rng(1, &[]);
+        let (a, b) = parents(5.0, 5.0);
+        let offspring = crossover(&a, &b, 0, rng);
+
+        assert_eq!(innovations(&offspring), vec![0, 1, 2, 3, 4, 5]);
+        assert!(offspring.find_neuron(&1).is_some());
+        assert!(offspring.find_neuron(&3).is_some());
+    }
+
+    #[test]
+    fn matching_genes_from_either_parent() {
+        let rng = &mut derive_rng(2, &[]);
+        let (a, b) = parents(1.0, 10.0);
+        let offspring = crossover(&a, &b, 0, rng);
+
+        for innovation in [0, 1] {
+            let weight = offspring.find_link_by_innovation(innovation).unwrap().weight;
+            assert!(weight == 1.0 || weight == 2.0);
+        }
+        // Non-matching genes keep the fitter parent's weight
+        assert_eq!(offspring.find_link_by_innovation(4).unwrap().weight, 2.0);
+    }
+
+    #[test]
+    fn disabled_if_disabled_in_either_parent() {
+        let rng = &mut derive_rng(3, &[]);
+        let (mut a, b) = parents(1.0, 10.0);
+        a.genome.find_link_mut(&LinkID { in_id: -1, out_id: 0 }).unwrap().is_enabled = false;
+
+        for offspring in [crossover(&a, &b, 0, rng), crossover(&b, &a, 0, rng)] {
+            assert!(!offspring.find_link_by_innovation(0).unwrap().is_enabled);
+            assert!(offspring.find_link_by_innovation(1).unwrap().is_enabled);
+        }
+    }
+}
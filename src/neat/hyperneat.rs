@@ -0,0 +1,141 @@
+use super::Genome;
+use super::activation::ActivationFunction;
+use super::aggregation::AggregationFunction;
+use super::nn::{CompiledNetwork, FeedForwardNeuralNetwork, Neuron, NeuronInput};
+
+/// The CPPN is queried with the coordinates of both ends of a link: (x1, y1, x2, y2)
+pub const CPPN_INPUTS: i32 = 4;
+/// One weight output per layer of links: input -> hidden and hidden -> output. Substrates
+/// without hidden nodes only use the first output, for input -> output links.
+pub const CPPN_OUTPUTS: i32 = 2;
+
+/// Node positions of a layered substrate, coordinates are in [-1, 1]
+#[derive(Clone, Debug)]
+pub struct Substrate {
+    pub inputs: Vec<(f32, f32)>,
+    pub hidden: Vec<(f32, f32)>,
+    pub outputs: Vec<(f32, f32)>,
+}
+
+/// Centers of the cells of a `cols` x `rows` grid spread over the given ranges, row by row
+pub fn grid(cols: usize, rows: usize, x_range: (f32, f32), y_range: (f32, f32)) -> Vec<(f32, f32)> {
+    let center = |i: usize, n: usize, (min, max): (f32, f32)| min + (max - min) * (i as f32 + 0.5) / n as f32;
+    (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (center(col, cols, x_range), center(row, rows, y_range))))
+        .collect()
+}
+
+impl Substrate {
+    /// Build the network the CPPN paints onto the substrate. Inputs get IDs -1, -2, ..., outputs
+    /// 0, 1, ... and hidden nodes follow the outputs, like neurons of a regular genome.
+    /// A link is only expressed where the CPPN output is larger than `substrate_weight_threshold`,
+    /// the rest of the output range is scaled to weights up to `substrate_max_weight`.
+    pub fn create_network(&self, cppn: &Genome) -> FeedForwardNeuralNetwork {
+        assert_eq!(
+            (cppn.num_inputs, cppn.num_outputs),
+            (CPPN_INPUTS, CPPN_OUTPUTS),
+            "HyperNEAT genomes must be CPPNs"
+        );
+        let config = crate::config::Config::global();
+        let mut cppn = CompiledNetwork::create_from_genome(cppn);
+        let mut cppn_outputs = [0.0; CPPN_OUTPUTS as usize];
+
+        let mut query = |from: (f32, f32), to: (f32, f32), output: usize| -> Option<f32> {
+            cppn.activate_into(&[from.0, from.1, to.0, to.1], &mut cppn_outputs);
+            let weight = cppn_outputs[output].clamp(-1.0, 1.0);
+            let threshold = config.substrate_weight_threshold;
+            if weight.abs() <= threshold {
+                return None;
+            }
+            Some(weight.signum() * (weight.abs() - threshold) / (1.0 - threshold) * config.substrate_max_weight)
+        };
+
+        let input_ids: Vec<i32> = (0..self.inputs.len() as i32).map(|i| -i - 1).collect();
+        let output_ids: Vec<i32> = (0..self.outputs.len() as i32).collect();
+        let hidden_ids: Vec<i32> = (0..self.hidden.len() as i32).map(|i| self.outputs.len() as i32 + i).collect();
+
+        let mut connect = |to: (f32, f32), sources: &[(f32, f32)], source_ids: &[i32], output: usize| -> Vec<NeuronInput> {
+            sources
+                .iter()
+                .zip(source_ids)
+                .filter_map(|(from, id)| {
+                    query(*from, to, output).map(|weight| NeuronInput { input_id: *id, weight })
+                })
+                .collect()
+        };
+
+        // Hidden nodes come first so every neuron's inputs are evaluated before it
+        let mut neurons: Vec<Neuron> = Vec::new();
+        for (position, id) in self.hidden.iter().zip(&hidden_ids) {
+            neurons.push(Neuron {
+                id: *id,
+                bias: 0.0,
+                activation: config.substrate_activation,
+                aggregation: AggregationFunction::Sum,
+                inputs: connect(*position, &self.inputs, &input_ids, 0),
+            });
+        }
+        for (position, id) in self.outputs.iter().zip(&output_ids) {
+            let inputs = if self.hidden.is_empty() {
+                connect(*position, &self.inputs, &input_ids, 0)
+            } else {
+                connect(*position, &self.hidden, &hidden_ids, 1)
+            };
+            neurons.push(Neuron {
+                id: *id,
+                bias: 0.0,
+                activation: ActivationFunction::Identity,
+                aggregation: AggregationFunction::Sum,
+                inputs,
+            });
+        }
+
+        FeedForwardNeuralNetwork {
+            input_ids,
+            output_ids,
+            neurons,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neat::LinkID;
+
+    #[test]
+    fn cppn_paints_weights_onto_substrate() {
+        let config = crate::config::Config::global();
+
+        // Weight of input -> output links is the x coordinate of the input (x1)
+        let mut cppn = Genome::new(0, CPPN_INPUTS, CPPN_OUTPUTS, &mut crate::neat::rng::derive_rng(0, &[]));
+        for neuron in cppn.neurons.iter_mut() {
+            neuron.bias = 0.0;
+        }
+        for link in cppn.links.iter_mut() {
+            link.weight = if link.id == (LinkID { in_id: -1, out_id: 0 }) { 1.0 } else { 0.0 };
+        }
+
+        let substrate = Substrate {
+            inputs: grid(5, 1, (-1.0, 1.0), (-1.0, -1.0)),
+            hidden: Vec::new(),
+            outputs: grid(2, 1, (-1.0, 1.0), (1.0, 1.0)),
+        };
+        let mut network = substrate.create_network(&cppn);
+
+        // Inputs at x = -0.8, -0.4, 0, 0.4, 0.8, only the middle one is below the threshold
+        assert_eq!(network.neurons.len(), 2);
+        for neuron in &network.neurons {
+            let sources: Vec<i32> = neuron.inputs.iter().map(|i| i.input_id).collect();
+            assert_eq!(sources, vec![-1, -2, -4, -5]);
+            let outer = neuron.inputs[3].weight;
+            let expected = (0.8 - config.substrate_weight_threshold) / (1.0 - config.substrate_weight_threshold)
+                * config.substrate_max_weight;
+            assert!((outer - expected).abs() < 1e-5);
+            assert!((neuron.inputs[0].weight + expected).abs() < 1e-5);
+        }
+
+        let outputs = network.activate(vec![1.0, 0.0, 0.0, 0.0, 1.0]);
+        assert!(outputs.iter().all(|o| o.abs() < 1e-5));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::{Genome, LinkID};
+
+#[derive(Debug, Default)]
+struct InnovationState {
+    next_innovation: i32,
+    next_neuron_id: i32,
+    // A link between two given neurons is always the same gene, so these never expire
+    link_innovations: HashMap<LinkID, i32>,
+    // Splitting the same link within one generation yields the same neuron (cleared every generation)
+    split_neurons: HashMap<i32, i32>,
+}
+
+/// Tracker counters at one point in time, see `InnovationTracker::renumber_since`
+#[derive(Clone, Copy, Debug)]
+pub struct InnovationMark {
+    next_innovation: i32,
+    next_neuron_id: i32,
+}
+
+/// Population-wide record of structural mutations (historical markings).
+/// Shared by reference between threads, the state lives behind a mutex.
+#[derive(Debug)]
+pub struct InnovationTracker {
+    state: Mutex<InnovationState>,
+}
+
+impl InnovationTracker {
+    /// Create a tracker that already knows the links of a fresh `Genome::new(num_inputs, num_outputs)`
+    pub fn new(num_inputs: i32, num_outputs: i32) -> Self {
+        let mut state = InnovationState {
+            next_innovation: 0,
+            next_neuron_id: num_outputs,
+            ..Default::default()
+        };
+
+        for i in 0..num_inputs {
+            for output_id in 0..num_outputs {
+                let link_id = LinkID { in_id: -i - 1, out_id: output_id };
+                state.link_innovations.insert(link_id, initial_link_innovation(i, output_id, num_outputs));
+                state.next_innovation += 1;
+            }
+        }
+
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Restore a tracker saved with a checkpoint. Splits are per generation and never saved.
+    pub fn restore(next_innovation: i32, next_neuron_id: i32, link_innovations: HashMap<LinkID, i32>) -> Self {
+        Self {
+            state: Mutex::new(InnovationState {
+                next_innovation,
+                next_neuron_id,
+                link_innovations,
+                split_neurons: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn next_innovation(&self) -> i32 {
+        self.state.lock().unwrap().next_innovation
+    }
+
+    pub fn next_neuron_id(&self) -> i32 {
+        self.state.lock().unwrap().next_neuron_id
+    }
+
+    /// Every link innovation seen so far, ordered by innovation number
+    pub fn link_innovations(&self) -> Vec<(LinkID, i32)> {
+        let state = self.state.lock().unwrap();
+        let mut innovations: Vec<(LinkID, i32)> =
+            state.link_innovations.iter().map(|(id, innovation)| (id.clone(), *innovation)).collect();
+        innovations.sort_by_key(|(_, innovation)| *innovation);
+        innovations
+    }
+
+    /// Innovation number of the link `id`, assigning a new one the first time it is seen
+    pub fn link_innovation(&self, id: &LinkID) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        if let Some(innovation) = state.link_innovations.get(id) {
+            return *innovation;
+        }
+
+        let innovation = state.next_innovation;
+        state.next_innovation += 1;
+        state.link_innovations.insert(id.clone(), innovation);
+        innovation
+    }
+
+    /// Neuron ID for splitting the link with the given innovation number
+    pub fn split_neuron(&self, link_innovation: i32) -> i32 {
+        let mut state = self.state.lock().unwrap();
+        if let Some(neuron_id) = state.split_neurons.get(&link_innovation) {
+            return *neuron_id;
+        }
+
+        let neuron_id = state.next_neuron_id;
+        state.next_neuron_id += 1;
+        state.split_neurons.insert(link_innovation, neuron_id);
+        neuron_id
+    }
+
+    /// Forget this generation's splits, so the same link split later is a new innovation
+    pub fn new_generation(&self) {
+        self.state.lock().unwrap().split_neurons.clear();
+    }
+
+    pub fn mark(&self) -> InnovationMark {
+        let state = self.state.lock().unwrap();
+        InnovationMark {
+            next_innovation: state.next_innovation,
+            next_neuron_id: state.next_neuron_id,
+        }
+    }
+
+    /// Renumber every innovation and neuron created since `mark` in the order they first appear
+    /// in `genomes`. Genomes mutated in parallel get their numbers in whatever order the threads
+    /// reach the tracker, renumbering afterwards makes the result independent of scheduling.
+    /// Structures that no genome kept are forgotten.
+    pub fn renumber_since<'a>(&self, mark: InnovationMark, genomes: impl Iterator<Item = &'a mut Genome>) {
+        let mut state = self.state.lock().unwrap();
+        let mut neuron_ids: HashMap<i32, i32> = HashMap::new();
+        let mut innovations: HashMap<i32, i32> = HashMap::new();
+        let mut link_innovations: HashMap<LinkID, i32> = HashMap::new();
+        let mut next_neuron_id = mark.next_neuron_id;
+        let mut next_innovation = mark.next_innovation;
+
+        for genome in genomes {
+            for neuron in genome.neurons.iter_mut().filter(|n| n.id >= mark.next_neuron_id) {
+                neuron.id = *neuron_ids.entry(neuron.id).or_insert_with(|| {
+                    next_neuron_id += 1;
+                    next_neuron_id - 1
+                });
+            }
+
+            for link in genome.links.iter_mut() {
+                link.id.in_id = *neuron_ids.get(&link.id.in_id).unwrap_or(&link.id.in_id);
+                link.id.out_id = *neuron_ids.get(&link.id.out_id).unwrap_or(&link.id.out_id);
+                if link.innovation >= mark.next_innovation {
+                    link.innovation = *innovations.entry(link.innovation).or_insert_with(|| {
+                        next_innovation += 1;
+                        next_innovation - 1
+                    });
+                    link_innovations.insert(link.id.clone(), link.innovation);
+                }
+            }
+        }
+
+        state.link_innovations.retain(|_, innovation| *innovation < mark.next_innovation);
+        state.link_innovations.extend(link_innovations);
+        state.split_neurons = state
+            .split_neurons
+            .iter()
+            .filter_map(|(innovation, neuron_id)| {
+                let innovation = if *innovation >= mark.next_innovation {
+                    *innovations.get(innovation)?
+                } else {
+                    *innovation
+                };
+                Some((innovation, *neuron_ids.get(neuron_id)?))
+            })
+            .collect();
+        state.next_innovation = next_innovation;
+        state.next_neuron_id = next_neuron_id;
+    }
+
+    /// Make the tracker aware of a genome that was created elsewhere (e.g. loaded from disk).
+    /// Links get their innovation numbers from the tracker, and future neuron IDs won't collide
+    /// with the genome's neurons.
+    pub fn register_genome(&self, genome: &mut Genome) {
+        for link in genome.links.iter_mut() {
+            link.innovation = self.link_innovation(&link.id);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(max_id) = genome.neurons.iter().map(|n| n.id).max() {
+            state.next_neuron_id = state.next_neuron_id.max(max_id + 1);
+        }
+    }
+}
+
+/// Innovation number of the input -> output link created by `Genome::new`
+pub fn initial_link_innovation(input_index: i32, output_id: i32, num_outputs: i32) -> i32 {
+    input_index * num_outputs + output_id
+}
@@ -0,0 +1,178 @@
+//! MAP-Elites (Mouret & Clune 2015): instead of one population converging on one way to play,
+//! keep the fittest genome found for every cell of a grid over behavior descriptors. New
+//! genomes are NEAT offspring of random elites, so the archive fills with playing styles.
+
+use std::collections::BTreeMap;
+
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use rayon::prelude::*;
+
+use super::crossover::crossover;
+use super::mutation::mutate;
+use super::innovation::InnovationTracker;
+use super::rng::{self, derive_rng};
+use super::{Genome, GenomeIdAllocator, Individual};
+
+/// Best individual found for a cell, with the descriptors that put it there
+#[derive(Clone)]
+pub struct Elite {
+    pub individual: Individual,
+    pub descriptors: Vec<f32>,
+}
+
+pub struct MapElites {
+    // Cells along every descriptor, descriptors are in [0, 1]
+    pub resolution: Vec<usize>,
+    pub cells: BTreeMap<Vec<usize>, Elite>,
+    pub innovations: InnovationTracker,
+    pub genome_ids: GenomeIdAllocator,
+    // Batches evaluated so far
+    pub generation: usize,
+    // Master seed, every random decision of the run is derived from it
+    pub seed: u64,
+}
+
+impl MapElites {
+    pub fn new(resolution: Vec<usize>, seed: u64) -> Self {
+        assert!(resolution.iter().all(|&n| n > 0), "every dimension needs at least one cell, got {:?}", resolution);
+        let (num_inputs, num_outputs) = crate::config::Config::global().genome_shape();
+        Self {
+            resolution,
+            cells: BTreeMap::new(),
+            innovations: InnovationTracker::new(num_inputs, num_outputs),
+            genome_ids: GenomeIdAllocator::new(1),
+            generation: 0,
+            seed,
+        }
+    }
+
+    pub fn num_cells(&self) -> usize {
+        self.resolution.iter().product()
+    }
+
+    /// Grid cell the descriptors fall into, values outside [0, 1] go to the border cells
+    pub fn cell(&self, descriptors: &[f32]) -> Vec<usize> {
+        assert_eq!(descriptors.len(), self.resolution.len(), "one descriptor per grid dimension");
+        descriptors
+            .iter()
+            .zip(&self.resolution)
+            .map(|(d, &n)| ((d.clamp(0.0, 1.0) * n as f32) as usize).min(n - 1))
+            .collect()
+    }
+
+    /// Keep the individual if its cell is empty or it is fitter than the cell's elite
+    pub fn insert(&mut self, individual: Individual, descriptors: Vec<f32>) -> bool {
+        let cell = self.cell(&descriptors);
+        if self.cells.get(&cell).is_some_and(|elite| elite.individual.fitness >= individual.fitness) {
+            return false;
+        }
+        self.cells.insert(cell, Elite { individual, descriptors });
+        true
+    }
+
+    /// Insert an evaluated batch from `next_batch`, `descriptors` in the same order as the
+    /// individuals. Returns how many individuals made it into the archive.
+    pub fn insert_batch(&mut self, individuals: Vec<Individual>, descriptors: Vec<Vec<f32>>) -> usize {
+        let inserted = individuals
+            .into_iter()
+            .zip(descriptors)
+            .filter(|(individual, descriptors)| self.insert(individual.clone(), descriptors.clone()))
+            .count();
+        self.generation += 1;
+        inserted
+    }
+
+    /// Genomes to evaluate next. While the archive is empty these are random genomes, after
+    /// that every genome is a mutated copy of a random elite, or a crossover of two.
+    pub fn next_batch(&self) -> Vec<Individual> {
+        let config = crate::config::Config::global();
+
+        if self.cells.is_empty() {
+            let mut rng = derive_rng(self.seed, &[rng::INIT, self.generation as u64]);
+            let (num_inputs, num_outputs) = config.genome_shape();
+            return (0..config.map_elites_batch_size)
+                .map(|_| Individual {
+                    genome: Genome::new(self.genome_ids.next_id(), num_inputs, num_outputs, &mut rng),
+                    fitness: 0.0,
+                })
+                .collect();
+        }
+
+        self.innovations.new_generation();
+        let elites: Vec<&Individual> = self.cells.values().map(|elite| &elite.individual).collect();
+        let ids: Vec<i32> = (0..config.map_elites_batch_size).map(|_| self.genome_ids.next_id()).collect();
+
+        // Every offspring has its own RNG stream, so it doesn't matter which thread builds it
+        let mark = self.innovations.mark();
+        let mut offspring: Vec<Individual> = ids
+            .par_iter()
+            .enumerate()
+            .map(|(index, &id)| {
+                let mut rng = derive_rng(self.seed, &[rng::REPRODUCE, self.generation as u64, index as u64]);
+                let p1 = elites.choose(&mut rng).unwrap();
+                let mut genome = if rng.random::<f32>() < config.map_elites_crossover_prob {
+                    let p2 = elites.choose(&mut rng).unwrap();
+                    crossover(p1, p2, id, &mut rng)
+                } else {
+                    Genome { id, ..p1.genome.clone() }
+                };
+                mutate(&mut genome, &self.innovations, &mut rng);
+                Individual { genome, fitness: 0.0 }
+            })
+            .collect();
+        self.innovations.renumber_since(mark, offspring.iter_mut().map(|i| &mut i.genome));
+        offspring
+    }
+
+    /// Elite of every filled cell, in cell order
+    pub fn elites(&self) -> Vec<&Elite> {
+        self.cells.values().collect()
+    }
+
+    pub fn best(&self) -> Option<&Elite> {
+        self.cells
+            .values()
+            .max_by(|a, b| a.individual.fitness.partial_cmp(&b.individual.fitness).unwrap())
+    }
+
+    /// Sum of the elites' fitness, grows with both quality and coverage
+    pub fn qd_score(&self) -> f32 {
+        self.cells.values().map(|elite| elite.individual.fitness).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_fittest_individual_per_cell() {
+        let mut archive = MapElites::new(vec![4, 2], 5);
+        assert_eq!(archive.cell(&[0.0, 0.49]), vec![0, 0]);
+        assert_eq!(archive.cell(&[0.3, 0.5]), vec![1, 1]);
+        assert_eq!(archive.cell(&[1.0, 2.0]), vec![3, 1]);
+
+        let batch = archive.next_batch();
+        let individual = |fitness: f32, i: usize| Individual { genome: batch[i].genome.clone(), fitness };
+        assert!(archive.insert(individual(10.0, 0), vec![0.1, 0.1]));
+        assert!(!archive.insert(individual(5.0, 1), vec![0.2, 0.2]));
+        assert!(archive.insert(individual(20.0, 2), vec![0.2, 0.2]));
+        assert!(archive.insert(individual(1.0, 3), vec![0.9, 0.9]));
+
+        assert_eq!(archive.cells.len(), 2);
+        assert_eq!(archive.cells[&vec![0, 0]].individual.genome.id, batch[2].genome.id);
+        assert_eq!(archive.qd_score(), 21.0);
+
+        // Offspring of the elites get fresh IDs
+        let offspring = archive.next_batch();
+        let max_id = batch.iter().map(|i| i.genome.id).max().unwrap();
+        assert!(offspring.iter().all(|i| i.genome.id > max_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one cell")]
+    fn rejects_dimensions_without_cells() {
+        MapElites::new(vec![10, 0, 10], 1);
+    }
+}
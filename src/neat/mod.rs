@@ -1,18 +1,37 @@
-use rand::{seq::IndexedRandom};
+use std::sync::atomic::{AtomicI32, Ordering};
 
+use rand::{Rng, seq::IndexedRandom};
+
+use crate::neat::activation::ActivationFunction;
+use crate::neat::aggregation::AggregationFunction;
 use crate::neat::mutation::new_value;
+pub mod activation;
+pub mod aggregation;
 pub mod crossover;
+pub mod hyperneat;
+pub mod innovation;
+pub mod map_elites;
 pub mod mutation;
+pub mod novelty;
+pub mod nsga2;
 pub mod population;
+pub mod rng;
+pub mod selection;
+pub mod species;
+#[cfg(test)]
+pub mod test_utils;
+pub mod validation;
 pub mod nn;
 
 #[derive(Clone, Debug)]
 pub struct NeuronGene {
     pub id: i32,
     pub bias: f32,
+    pub activation: ActivationFunction,
+    pub aggregation: AggregationFunction,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct LinkID {
     pub in_id: i32,
     pub out_id: i32,
@@ -23,6 +42,8 @@ pub struct LinkGene {
     pub id: LinkID,
     pub weight: f32,
     pub is_enabled: bool,
+    // Historical marking, see `innovation::InnovationTracker`
+    pub innovation: i32,
 }
 
 #[derive(Clone, Debug)]
@@ -34,18 +55,39 @@ pub struct Genome {
     pub links: Vec<LinkGene>,
 }
 
-static mut GENOME_INDEXER: i32 = 0;
-impl Genome {
-    pub fn genome_indexer() -> i32 {
-        unsafe {
-            GENOME_INDEXER += 1;
-            GENOME_INDEXER
+/// Hands out genome IDs. Every population owns one, so populations never share IDs and
+/// genomes can be created from any thread.
+#[derive(Debug)]
+pub struct GenomeIdAllocator {
+    next: AtomicI32,
+}
+
+impl GenomeIdAllocator {
+    pub fn new(next: i32) -> Self {
+        Self {
+            next: AtomicI32::new(next),
         }
     }
 
-    pub fn new(num_in: i32, num_out: i32) -> Genome {
+    pub fn next_id(&self) -> i32 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// The ID the next genome will get, saved with checkpoints
+    pub fn peek(&self) -> i32 {
+        self.next.load(Ordering::Relaxed)
+    }
+
+    /// Make sure `id` is never handed out again, e.g. after loading a genome from disk
+    pub fn skip_past(&self, id: i32) {
+        self.next.fetch_max(id + 1, Ordering::Relaxed);
+    }
+}
+
+impl Genome {
+    pub fn new(id: i32, num_in: i32, num_out: i32, rng: &mut impl Rng) -> Genome {
         let mut new_genome = Genome {
-            id: Genome::genome_indexer(),
+            id,
             num_inputs: num_in,
             num_outputs: num_out,
             neurons: Vec::new(),
@@ -53,14 +95,19 @@ impl Genome {
         };
 
         for i in 0..num_out {
-            new_genome.neurons.push(NeuronGene {id: i, bias: new_value()})
+            new_genome.neurons.push(NeuronGene {id: i, bias: new_value(rng), activation: ActivationFunction::default_for(i, num_out), aggregation: AggregationFunction::Sum})
         }
 
         for i in 0.. num_in {
             let input_id = -i - 1;
-            new_genome.neurons.push(NeuronGene {id: input_id, bias: new_value()});
+            new_genome.neurons.push(NeuronGene {id: input_id, bias: new_value(rng), activation: ActivationFunction::default_for(input_id, num_out), aggregation: AggregationFunction::Sum});
             for output_id in 0..num_out {
-                new_genome.links.push(LinkGene {id: LinkID { in_id: input_id, out_id: output_id }, weight: new_value(), is_enabled: true })
+                new_genome.links.push(LinkGene {
+                    id: LinkID { in_id: input_id, out_id: output_id },
+                    weight: new_value(rng),
+                    is_enabled: true,
+                    innovation: innovation::initial_link_innovation(i, output_id, num_out),
+                })
             }
         }
         new_genome
@@ -74,6 +121,10 @@ impl Genome {
         self.links.iter().find(|x| x.id == *id)
     }
 
+    pub fn find_link_by_innovation(&self, innovation: i32) -> Option<&LinkGene> {
+        self.links.iter().find(|x| x.innovation == innovation)
+    }
+
     pub fn find_link_mut(&mut self, id: &LinkID) -> Option<&mut LinkGene> {
         self.links.iter_mut().find(|x| x.id == *id)
     }
@@ -1,13 +1,13 @@
-use rand::{random_range, seq::IndexedMutRandom};
+use rand::{Rng, seq::IndexedRandom};
 use rand_distr::{Normal, Distribution};
 
 use crate::neat::*;
+use crate::neat::innovation::InnovationTracker;
 
 // Structural Mutations
-fn choose_random_input_or_hidden(genome: &Genome) -> i32 {
+fn choose_random_input_or_hidden(genome: &Genome, rng: &mut impl Rng) -> i32 {
     loop {
-        let mut rng = rand::rng();
-        let chosen = genome.neurons.choose(&mut rng).unwrap();
+        let chosen = genome.neurons.choose(rng).unwrap();
         if chosen.id < genome.num_outputs && chosen.id >= 0 {
             // output neuron
             continue;
@@ -16,10 +16,9 @@ fn choose_random_input_or_hidden(genome: &Genome) -> i32 {
     }
 }
 
-fn choose_random_output_or_hidden(genome: &Genome) -> i32 {
+fn choose_random_output_or_hidden(genome: &Genome, rng: &mut impl Rng) -> i32 {
     loop {
-        let mut rng = rand::rng();
-        let chosen = genome.neurons.choose(&mut rng).unwrap();
+        let chosen = genome.neurons.choose(rng).unwrap();
         if chosen.id < 0 {
             // input neuron
             continue;
@@ -28,7 +27,7 @@ fn choose_random_output_or_hidden(genome: &Genome) -> i32 {
     }
 }
 
-fn choose_random_hidden(genome: &Genome) -> Option<i32> {
+fn choose_random_hidden(genome: &Genome, rng: &mut impl Rng) -> Option<i32> {
     // Check if there are any hidden neurons
     let hidden_neurons: Vec<&NeuronGene> = genome.neurons.iter()
         .filter(|n| n.id >= genome.num_outputs)
@@ -38,10 +37,9 @@ fn choose_random_hidden(genome: &Genome) -> Option<i32> {
         return None;
     }
     
-    let mut rng = rand::rng();
-    Some(hidden_neurons.choose(&mut rng).unwrap().id)
+    Some(hidden_neurons.choose(rng).unwrap().id)
 }
-fn would_create_cycle(links: &Vec<LinkGene>, in_id: i32, out_id: i32) -> bool {
+pub fn would_create_cycle(links: &Vec<LinkGene>, in_id: i32, out_id: i32) -> bool {
     if in_id == out_id {
         return true;
     }
@@ -68,9 +66,16 @@ fn would_create_cycle(links: &Vec<LinkGene>, in_id: i32, out_id: i32) -> bool {
     false
 }
 
-pub fn mutate_add_link(genome: &mut Genome) -> Option<()> {
-    let input_id = choose_random_input_or_hidden(genome);
-    let output_id = choose_random_output_or_hidden(genome);
+pub fn mutate_add_link(genome: &mut Genome, innovations: &InnovationTracker, rng: &mut impl Rng) -> Option<()> {
+    let config = crate::config::Config::global();
+
+    // Recurrent networks may also feed outputs back into the network
+    let input_id = if config.allow_recurrent {
+        genome.neurons.choose(rng).unwrap().id
+    } else {
+        choose_random_input_or_hidden(genome, rng)
+    };
+    let output_id = choose_random_output_or_hidden(genome, rng);
     let link_id = LinkID {
         in_id: input_id,
         out_id: output_id,
@@ -82,14 +87,15 @@ pub fn mutate_add_link(genome: &mut Genome) -> Option<()> {
         return None;
     }
 
-    // Only support feed forwards with no cycle
-    if would_create_cycle(&genome.links, input_id, output_id) {
+    // Feed forward networks can't have cycles (self links included)
+    if !config.allow_recurrent && would_create_cycle(&genome.links, input_id, output_id) {
         return None;
     }
 
     let new_link = LinkGene {
+        innovation: innovations.link_innovation(&link_id),
         id: link_id,
-        weight: (rand::random::<f32>() * 2.0 - 1.0), // Random weight between -1 and 1
+        weight: (rng.random::<f32>() * 2.0 - 1.0), // Random weight between -1 and 1
         is_enabled: true,
     };
     genome.links.push(new_link);
@@ -97,43 +103,53 @@ pub fn mutate_add_link(genome: &mut Genome) -> Option<()> {
     Some(())
 }
 
-pub fn mutate_remove_link(genome: &mut Genome) -> Option<()> {
+pub fn mutate_remove_link(genome: &mut Genome, rng: &mut impl Rng) -> Option<()> {
     if genome.links.is_empty() {
         return None;
     }
 
     // Generate index from 0 to links.len() - 1 (valid Vec indices)
-    let to_remove_id = random_range(0..(genome.links.len() as i32));
+    let to_remove_id = rng.random_range(0..(genome.links.len() as i32));
 
     genome.links.remove(to_remove_id as usize);
     Some(())
 }
 
-pub fn mutate_add_neuron(genome: &mut Genome) -> Option<()> {
+pub fn mutate_add_neuron(genome: &mut Genome, innovations: &InnovationTracker, rng: &mut impl Rng) -> Option<()> {
     if genome.links.is_empty() {
         return None;
     }
 
-    let mut rng = rand::rng();
-    let link_to_split: &mut LinkGene = genome.links.choose_mut(&mut rng).unwrap();
-    link_to_split.is_enabled = false; // Disable the original link when splitting
+    let split_index = rng.random_range(0..genome.links.len());
+    let link_to_split = &genome.links[split_index];
+    let link_id = link_to_split.id.clone();
+    let prev_weight = link_to_split.weight;
 
     let new_neuron = NeuronGene {
-        id: genome.neurons.len() as i32,
-        bias: (rand::random::<f32>() * 2.0 - 1.0), // Random bias between -1 and 1
+        id: innovations.split_neuron(link_to_split.innovation),
+        bias: (rng.random::<f32>() * 2.0 - 1.0), // Random bias between -1 and 1
+        activation: crate::config::Config::global().default_activation,
+        aggregation: crate::config::Config::global().default_aggregation,
     };
-    genome.neurons.push(new_neuron.clone());
 
-    let link_id = link_to_split.id.clone();
-    let prev_weight = link_to_split.weight;
+    // Never duplicate a neuron the genome already has
+    if genome.find_neuron(&new_neuron.id).is_some() {
+        return None;
+    }
+    genome.links[split_index].is_enabled = false; // Disable the original link when splitting
+    genome.neurons.push(new_neuron.clone());
 
+    let in_link = LinkID {in_id: link_id.in_id, out_id: new_neuron.id};
     genome.links.push(LinkGene {
-        id: LinkID {in_id: link_id.in_id, out_id: new_neuron.id},
+        innovation: innovations.link_innovation(&in_link),
+        id: in_link,
         weight: 1.0,
         is_enabled: true,
     });
+    let out_link = LinkID {in_id: new_neuron.id, out_id: link_id.out_id};
     genome.links.push(LinkGene {
-        id: LinkID {in_id: new_neuron.id, out_id: link_id.out_id},
+        innovation: innovations.link_innovation(&out_link),
+        id: out_link,
         weight: prev_weight,
         is_enabled: true,
     });
@@ -141,12 +157,12 @@ pub fn mutate_add_neuron(genome: &mut Genome) -> Option<()> {
     Some(())
 }
 
-pub fn mutate_remove_neuron(genome: &mut Genome) -> Option<()> {
+pub fn mutate_remove_neuron(genome: &mut Genome, rng: &mut impl Rng) -> Option<()> {
     if genome.links.is_empty() {
         return None;
     }
 
-    let random_neuron_id = choose_random_hidden(&genome)?;
+    let random_neuron_id = choose_random_hidden(genome, rng)?;
 
     // Remove associated links with this neuron
     genome.links.retain(|x| x.id.in_id != random_neuron_id && x.id.out_id != random_neuron_id);
@@ -158,16 +174,34 @@ pub fn mutate_remove_neuron(genome: &mut Genome) -> Option<()> {
     Some(())
 }
 
+pub fn mutate_enable_link(genome: &mut Genome, rng: &mut impl Rng) -> Option<()> {
+    let disabled: Vec<usize> = (0..genome.links.len()).filter(|&i| !genome.links[i].is_enabled).collect();
+    let chosen = *disabled.choose(rng)?;
+
+    // Disabled links are still taken into account by the cycle check, so enabling one is safe
+    genome.links[chosen].is_enabled = true;
+    Some(())
+}
+
+pub fn mutate_disable_link(genome: &mut Genome, rng: &mut impl Rng) -> Option<()> {
+    let enabled: Vec<usize> = (0..genome.links.len()).filter(|&i| genome.links[i].is_enabled).collect();
+    let chosen = *enabled.choose(rng)?;
+
+    genome.links[chosen].is_enabled = false;
+    Some(())
+}
+
+#[derive(Clone, Copy, Debug)]
 enum MutationsType {
     AddLink,
-    // RemoveLink,
+    RemoveLink,
     AddNeuron,
-    // RemoveNeuron,
-    MutateWeights,
-    MutateBiases,
+    RemoveNeuron,
+    EnableLink,
+    DisableLink,
 }
 
-pub fn mutate_weights(genome: &mut Genome) -> Option<()> {
+pub fn mutate_weights(genome: &mut Genome, rng: &mut impl Rng) -> Option<()> {
     if genome.links.is_empty() {
         return None;
     }
@@ -175,21 +209,15 @@ pub fn mutate_weights(genome: &mut Genome) -> Option<()> {
     let config = crate::config::Config::global();
     
     for link in genome.links.iter_mut() {
-        if rand::random::<f32>() < config.mutation_rate {
-            if rand::random::<f32>() < config.shift_weight_prob {
-                // Small adjustment to existing weight
-                link.weight = mutate_delta(link.weight);
-            } else if rand::random::<f32>() < config.random_weight_prob {
-                // Replace with completely new random weight
-                link.weight = new_value();
-            }
+        if rng.random::<f32>() < config.mutation_rate {
+            link.weight = mutate_value(link.weight, config.shift_weight_prob, config.random_weight_prob, rng);
         }
     }
     
     Some(())
 }
 
-pub fn mutate_biases(genome: &mut Genome) -> Option<()> {
+pub fn mutate_biases(genome: &mut Genome, rng: &mut impl Rng) -> Option<()> {
     if genome.neurons.is_empty() {
         return None;
     }
@@ -197,48 +225,103 @@ pub fn mutate_biases(genome: &mut Genome) -> Option<()> {
     let config = crate::config::Config::global();
     
     for neuron in genome.neurons.iter_mut() {
-        if rand::random::<f32>() < config.mutation_rate {
-            if rand::random::<f32>() < config.shift_weight_prob {
-                // Small adjustment to existing bias
-                neuron.bias = mutate_delta(neuron.bias);
-            } else if rand::random::<f32>() < config.random_weight_prob {
-                // Replace with completely new random bias
-                neuron.bias = new_value();
-            }
+        if rng.random::<f32>() < config.mutation_rate {
+            neuron.bias = mutate_value(neuron.bias, config.shift_weight_prob, config.replace_rate, rng);
         }
     }
     
     Some(())
 }
 
-pub fn mutate(genome: &mut Genome) -> Option<()> {
-    // Always mutate weights and biases (most important for learning!)
-    mutate_weights(genome);
-    mutate_biases(genome);
-    
-    // Also do structural mutations with some probability
+pub fn mutate_activations(genome: &mut Genome, rng: &mut impl Rng) -> Option<()> {
     let config = crate::config::Config::global();
-    
-    if rand::random::<f32>() < config.add_link_prob {
-        mutate_add_link(genome);
+    if config.activation_options.is_empty() {
+        return None;
     }
-    
-    if rand::random::<f32>() < config.add_node_prob {
-        mutate_add_neuron(genome);
+
+    // Input neurons are never activated, only mutate outputs and hidden neurons
+    for neuron in genome.neurons.iter_mut().filter(|n| n.id >= 0) {
+        if rng.random::<f32>() < config.activation_mutate_prob {
+            neuron.activation = *config.activation_options.choose(rng).unwrap();
+        }
     }
+
+    Some(())
+}
+
+pub fn mutate_aggregations(genome: &mut Genome, rng: &mut impl Rng) -> Option<()> {
+    let config = crate::config::Config::global();
+    if config.aggregation_options.is_empty() {
+        return None;
+    }
+
+    for neuron in genome.neurons.iter_mut().filter(|n| n.id >= 0) {
+        if rng.random::<f32>() < config.aggregation_mutate_prob {
+            neuron.aggregation = *config.aggregation_options.choose(rng).unwrap();
+        }
+    }
+
+    Some(())
+}
+
+pub fn mutate(genome: &mut Genome, innovations: &InnovationTracker, rng: &mut impl Rng) -> Option<()> {
+    // Always mutate weights and biases (most important for learning!)
+    mutate_weights(genome, rng);
+    mutate_biases(genome, rng);
+    mutate_activations(genome, rng);
+    mutate_aggregations(genome, rng);
     
+    // Every structural mutation happens independently with its own probability
+    let config = crate::config::Config::global();
+    let structural_mutations = [
+        (MutationsType::AddLink, config.add_link_prob),
+        (MutationsType::RemoveLink, config.remove_link_prob),
+        (MutationsType::AddNeuron, config.add_node_prob),
+        (MutationsType::RemoveNeuron, config.remove_node_prob),
+        (MutationsType::EnableLink, config.enable_link_prob),
+        (MutationsType::DisableLink, config.disable_link_prob),
+    ];
+
+    for (mutation, probability) in structural_mutations {
+        if rng.random::<f32>() >= probability {
+            continue;
+        }
+        match mutation {
+            MutationsType::AddLink => mutate_add_link(genome, innovations, rng),
+            MutationsType::RemoveLink => mutate_remove_link(genome, rng),
+            MutationsType::AddNeuron => mutate_add_neuron(genome, innovations, rng),
+            MutationsType::RemoveNeuron => mutate_remove_neuron(genome, rng),
+            MutationsType::EnableLink => mutate_enable_link(genome, rng),
+            MutationsType::DisableLink => mutate_disable_link(genome, rng),
+        };
+    }
+
+    debug_assert!(genome.validate().is_ok(), "mutation left genome {} invalid: {:?}", genome.id, genome.validate());
+
     Some(())
 }
 
 // Unstructured Mutations
-pub fn new_value() -> f32 {
+pub fn new_value(rng: &mut impl Rng) -> f32 {
     let config = crate::config::Config::global();
-    clamp(Normal::new(config.init_mean, config.init_stdev).unwrap().sample(&mut rand::rng()))
+    clamp(Normal::new(config.init_mean, config.init_stdev).unwrap().sample(rng))
 }
 
-pub fn mutate_delta(value: f32) -> f32 {
+/// Shift `value` a little with probability `shift_prob`, otherwise replace it with a completely
+/// new random value with probability `replace_prob`
+pub fn mutate_value(value: f32, shift_prob: f32, replace_prob: f32, rng: &mut impl Rng) -> f32 {
+    if rng.random::<f32>() < shift_prob {
+        mutate_delta(value, rng)
+    } else if rng.random::<f32>() < replace_prob {
+        new_value(rng)
+    } else {
+        value
+    }
+}
+
+pub fn mutate_delta(value: f32, rng: &mut impl Rng) -> f32 {
     let config = crate::config::Config::global();
-    let delta = clamp(Normal::new(0.0, config.mutate_power).unwrap().sample(&mut rand::rng()));
+    let delta = clamp(Normal::new(0.0, config.mutate_power).unwrap().sample(rng));
     clamp(value + delta)
 }
 
@@ -246,3 +329,58 @@ pub fn clamp(x: f32) -> f32 {
     let config = crate::config::Config::global();
     f32::min(config.max, f32::max(config.min, x))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removals_keep_inputs_outputs_and_links_valid() {
+        let rng = &mut crate::neat::rng::derive_rng(13, &[]);
+        let innovations = InnovationTracker::new(3, 3);
+        let mut genome = Genome::new(0, 3, 3, rng);
+        for _ in 0..30 {
+            mutate_add_neuron(&mut genome, &innovations, rng);
+            mutate_add_link(&mut genome, &innovations, rng);
+            innovations.new_generation();
+        }
+
+        for _ in 0..100 {
+            mutate_remove_neuron(&mut genome, rng);
+            mutate_remove_link(&mut genome, rng);
+
+            for id in genome.make_input_ids().iter().chain(genome.make_output_ids().iter()) {
+                assert!(genome.find_neuron(id).is_some(), "neuron {} was removed", id);
+            }
+            for link in &genome.links {
+                assert!(genome.find_neuron(&link.id.in_id).is_some());
+                assert!(genome.find_neuron(&link.id.out_id).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn biases_are_replaced_at_replace_rate() {
+        let config = crate::config::Config::global();
+        let rng = &mut crate::neat::rng::derive_rng(13, &[]);
+
+        // Only the replace probability decides when nothing is shifted
+        assert!((0..100).all(|_| mutate_value(config.max, 0.0, 0.0, rng) == config.max));
+        assert!((0..100).all(|_| mutate_value(config.max, 0.0, 1.0, rng) != config.max));
+
+        // Biases at the maximum only drop far below it when they are replaced
+        assert_ne!(config.replace_rate, config.random_weight_prob);
+        let mut genome = Genome::new(0, 3, 3, rng);
+        genome.neurons.resize(1000, genome.neurons[0].clone());
+        let mut replaced = 0;
+        for _ in 0..1000 {
+            for neuron in genome.neurons.iter_mut() {
+                neuron.bias = config.max;
+            }
+            mutate_biases(&mut genome, rng);
+            replaced += genome.neurons.iter().filter(|n| n.bias < config.max - 3.0 * config.mutate_power * 3.0).count();
+        }
+        let expected = 1_000_000.0 * config.mutation_rate * (1.0 - config.shift_weight_prob) * config.replace_rate;
+        assert!((replaced as f32 - expected).abs() < 0.2 * expected, "{} replaced, expected {}", replaced, expected);
+    }
+}
@@ -1,4 +1,6 @@
 use super::{Genome, LinkGene};
+use super::activation::ActivationFunction;
+use super::aggregation::AggregationFunction;
 use std::collections::{HashMap, HashSet};
 
 pub fn relu(x: f32) -> f32 {
@@ -15,91 +17,103 @@ pub struct NeuronInput {
 pub struct Neuron {
     pub id: i32,
     pub bias: f32,
+    pub activation: ActivationFunction,
+    pub aggregation: AggregationFunction,
     pub inputs: Vec<NeuronInput>,
 }
 
+/// Nodes (other than inputs) that can influence the outputs, found by walking the links
+/// backwards from the outputs. Walking stops at the inputs.
 pub fn required_for_output(
     inputs: &Vec<i32>,
     outputs: &Vec<i32>,
     links: &Vec<LinkGene>,
 ) -> HashSet<i32> {
-    let mut required = HashSet::new();
-    for i in outputs {
-        required.insert(*i);
+    let mut incoming: HashMap<i32, Vec<i32>> = HashMap::new();
+    for link in links {
+        incoming.entry(link.id.out_id).or_default().push(link.id.in_id);
     }
-    let mut s = required.clone();
 
-    loop {
-        // find the nodes in the links that outputs to the current layer, and input not in the
-        // current layer
-        let t: HashSet<i32> = links
-            .clone()
-            .iter()
-            .filter(|x| s.contains(&x.id.out_id) && !s.contains(&x.id.in_id))
-            .map(|x| x.id.in_id)
-            .collect();
+    let inputs: HashSet<i32> = inputs.iter().copied().collect();
+    let mut required: HashSet<i32> = outputs.iter().copied().collect();
+    let mut visited = required.clone();
+    let mut frontier: Vec<i32> = outputs.clone();
 
-        if t.is_empty() {
-            break;
+    loop {
+        // Nodes feeding into the current layer that haven't been visited yet
+        let mut t: Vec<i32> = Vec::new();
+        for node in &frontier {
+            for in_id in incoming.get(node).into_iter().flatten() {
+                if visited.insert(*in_id) {
+                    t.push(*in_id);
+                }
+            }
         }
 
-        // Why copied? because filter returns an iterator of references
-        // only add nodes that is not in the input nodes
-        let layer_nodes: HashSet<i32> = t.iter().filter(|x| !inputs.contains(x)).copied().collect();
-        if layer_nodes.is_empty() {
+        // Only input nodes left, nothing more to walk through
+        if t.iter().all(|x| inputs.contains(x)) {
             break;
         }
 
-        required = required.union(&layer_nodes).copied().collect(); // or &required | &layer_nodes
-        s = s.union(&t).copied().collect();
+        required.extend(t.iter().filter(|x| !inputs.contains(x)));
+        frontier = t;
     }
 
     required
 }
 
+/// Group the required nodes into layers that can be evaluated in order: every node's required
+/// inputs are in an earlier layer. This is a topological sort (Kahn's algorithm) done one
+/// layer at a time.
 pub fn feed_forward_layers(
     inputs: &Vec<i32>,
     outputs: &Vec<i32>,
     links: &Vec<LinkGene>,
 ) -> Vec<Vec<i32>> {
     let mut layers: Vec<Vec<i32>> = Vec::new();
-    let required = required_for_output(&inputs, &outputs, &links);
-
-    let mut potential_input: HashSet<i32> = inputs.iter().copied().collect();
-
-    loop {
-        // Candidate nodes c for the next layer. The nodes should connect a node IN s to a node
-        // NOT IN s
-        let c: HashSet<i32> = links
-            .clone()
-            .iter()
-            .filter(|x| {
-                potential_input.contains(&x.id.in_id) && !potential_input.contains(&x.id.out_id)
-            })
-            .map(|x| x.id.out_id)
-            .collect();
+    let required = required_for_output(inputs, outputs, links);
+
+    let mut outgoing: HashMap<i32, Vec<i32>> = HashMap::new();
+    // For every node, the required nodes it still waits on
+    let mut pending: HashMap<i32, HashSet<i32>> = HashMap::new();
+    for link in links {
+        outgoing.entry(link.id.in_id).or_default().push(link.id.out_id);
+        if required.contains(&link.id.in_id) {
+            pending.entry(link.id.out_id).or_default().insert(link.id.in_id);
+        }
+    }
 
-        // Keep only the used nodes whose entire input set is contained in s
-        let mut next_layer: Vec<i32> = Vec::new();
-        for n in c {
-            let inputs_to_n: Vec<i32> = links
-                .clone()
-                .iter()
-                .filter(|x| x.id.out_id == n && required.contains(&x.id.in_id))
-                .map(|x| x.id.in_id)
-                .collect();
+    let mut evaluated: HashSet<i32> = inputs.iter().copied().collect();
+    let mut ready: HashSet<i32> = HashSet::new();
 
-            if required.contains(&n) && inputs_to_n.iter().all(|x| potential_input.contains(x)) {
-                next_layer.push(n);
+    // Only nodes linked from a newly evaluated node can become ready
+    let mut visit = |from: &[i32], evaluated: &HashSet<i32>, ready: &mut HashSet<i32>| {
+        for node in from {
+            for out_id in outgoing.get(node).into_iter().flatten() {
+                if let Some(waiting_on) = pending.get_mut(out_id) {
+                    waiting_on.remove(node);
+                }
             }
         }
-
-        if next_layer.is_empty() {
-            break;
+        for node in from {
+            for out_id in outgoing.get(node).into_iter().flatten() {
+                let done = pending.get(out_id).is_none_or(|waiting_on| waiting_on.is_empty());
+                if required.contains(out_id) && !evaluated.contains(out_id) && done {
+                    ready.insert(*out_id);
+                }
+            }
         }
+    };
+
+    visit(inputs, &evaluated, &mut ready);
 
-        layers.push(next_layer.clone());
-        potential_input = &potential_input | &next_layer.iter().copied().collect();
+    while !ready.is_empty() {
+        let mut next_layer: Vec<i32> = ready.drain().collect();
+        next_layer.sort();
+
+        evaluated.extend(next_layer.iter().copied());
+        visit(&next_layer, &evaluated, &mut ready);
+        layers.push(next_layer);
     }
 
     layers
@@ -129,20 +143,16 @@ impl FeedForwardNeuralNetwork {
         }
 
         for neuron in self.neurons.clone() {
-            let mut value = 0.0;
             for input in &neuron.inputs {
                 if !values.contains_key(&input.input_id) {
                     panic!("Missing input {} for neuron {}", input.input_id, neuron.id);
                 }
-                value += *(values.get(&input.input_id).unwrap()) * input.weight;
             }
+            let mut value = neuron
+                .aggregation
+                .aggregate(neuron.inputs.iter().map(|input| values[&input.input_id] * input.weight));
             value += neuron.bias;
-
-            // Only apply ReLU to hidden neurons, not output neurons
-            // Output neurons need to be able to express negative values for comparison
-            if !self.output_ids.contains(&neuron.id) {
-                value = relu(value);
-            }
+            value = neuron.activation.apply(value);
 
             values.insert(neuron.id, value);
         }
@@ -170,22 +180,24 @@ impl FeedForwardNeuralNetwork {
 
         let layers = feed_forward_layers(&inputs, &outputs, &enabled_links);
 
+        let mut incoming: HashMap<i32, Vec<NeuronInput>> = HashMap::new();
+        for link in &enabled_links {
+            incoming.entry(link.id.out_id).or_default().push(NeuronInput {
+                input_id: link.id.in_id,
+                weight: link.weight,
+            });
+        }
+
         let mut neurons: Vec<Neuron> = Vec::new();
         for layer in layers {
             for neuron_id in layer {
-                let mut neuron_inputs: Vec<NeuronInput> = Vec::new();
-                for link in enabled_links.clone() {
-                    if neuron_id == link.id.out_id {
-                        neuron_inputs.push(NeuronInput {
-                            input_id: link.id.in_id,
-                            weight: link.weight,
-                        });
-                    }
-                }
+                let neuron_inputs = incoming.remove(&neuron_id).unwrap_or_default();
                 if let Some(neuron_gene) = genome.find_neuron(&neuron_id) {
                     neurons.push(Neuron {
                         id: neuron_gene.id,
                         bias: neuron_gene.bias,
+                        activation: neuron_gene.activation,
+                        aggregation: neuron_gene.aggregation,
                         inputs: neuron_inputs,
                     })
                 }
@@ -199,10 +211,253 @@ impl FeedForwardNeuralNetwork {
     }
 }
 
+/// Common interface of the network phenotypes, so callers don't care which one the config picked
+pub trait NeuralNetwork {
+    fn num_outputs(&self) -> usize;
+    /// Evaluate the network, writing one value per output into `outputs`
+    fn activate_into(&mut self, inputs: &[f32], outputs: &mut [f32]);
+    /// Forget any state kept between activations
+    fn reset(&mut self);
+
+    fn activate(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+        let mut outputs = vec![0.0; self.num_outputs()];
+        self.activate_into(&inputs, &mut outputs);
+        outputs
+    }
+}
+
+impl NeuralNetwork for FeedForwardNeuralNetwork {
+    fn num_outputs(&self) -> usize {
+        self.output_ids.len()
+    }
+
+    fn activate_into(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        outputs.copy_from_slice(&FeedForwardNeuralNetwork::activate(self, inputs.to_vec()));
+    }
+
+    // Feed forward networks are stateless
+    fn reset(&mut self) {}
+}
+
+#[derive(Debug, PartialEq)]
+struct CompiledNeuron {
+    slot: usize,
+    bias: f32,
+    activation: ActivationFunction,
+    aggregation: AggregationFunction,
+    // Range into `CompiledNetwork::inputs`
+    inputs_start: usize,
+    inputs_end: usize,
+}
+
+/// Feed forward network with neuron IDs remapped to dense indices, evaluated into a buffer
+/// that is reused between activations. Gives the same outputs as `FeedForwardNeuralNetwork`.
+#[derive(Debug, PartialEq)]
+pub struct CompiledNetwork {
+    num_inputs: usize,
+    output_slots: Vec<usize>,
+    neurons: Vec<CompiledNeuron>,
+    // (slot, weight) of every neuron's inputs, back to back
+    inputs: Vec<(usize, f32)>,
+    values: Vec<f32>,
+}
+
+impl CompiledNetwork {
+    pub fn create_from_genome(genome: &Genome) -> CompiledNetwork {
+        CompiledNetwork::compile(&FeedForwardNeuralNetwork::create_from_genome(genome))
+    }
+
+    pub fn compile(network: &FeedForwardNeuralNetwork) -> CompiledNetwork {
+        // Slots: inputs first, then outputs, then hidden neurons in evaluation order
+        let mut slots: HashMap<i32, usize> = HashMap::new();
+        for id in network.input_ids.iter().chain(network.output_ids.iter()) {
+            let slot = slots.len();
+            slots.entry(*id).or_insert(slot);
+        }
+        for neuron in &network.neurons {
+            let slot = slots.len();
+            slots.entry(neuron.id).or_insert(slot);
+        }
+
+        let mut neurons = Vec::with_capacity(network.neurons.len());
+        let mut inputs = Vec::new();
+        for neuron in &network.neurons {
+            let inputs_start = inputs.len();
+            for input in &neuron.inputs {
+                let slot = match slots.get(&input.input_id) {
+                    Some(slot) => *slot,
+                    None => panic!("Missing input {} for neuron {}", input.input_id, neuron.id),
+                };
+                inputs.push((slot, input.weight));
+            }
+            neurons.push(CompiledNeuron {
+                slot: slots[&neuron.id],
+                bias: neuron.bias,
+                activation: neuron.activation,
+                aggregation: neuron.aggregation,
+                inputs_start,
+                inputs_end: inputs.len(),
+            });
+        }
+
+        CompiledNetwork {
+            num_inputs: network.input_ids.len(),
+            output_slots: network.output_ids.iter().map(|id| slots[id]).collect(),
+            neurons,
+            inputs,
+            values: vec![0.0; slots.len()],
+        }
+    }
+
+    pub fn activate_into(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        assert!(inputs.len() == self.num_inputs);
+        assert!(outputs.len() == self.output_slots.len());
+
+        self.values[..self.num_inputs].copy_from_slice(inputs);
+        // Outputs that aren't connected to anything stay 0
+        self.values[self.num_inputs..].fill(0.0);
+
+        for neuron in &self.neurons {
+            let values = &self.values;
+            let mut value = neuron.aggregation.aggregate(
+                self.inputs[neuron.inputs_start..neuron.inputs_end]
+                    .iter()
+                    .map(|(slot, weight)| values[*slot] * weight),
+            );
+            value += neuron.bias;
+            self.values[neuron.slot] = neuron.activation.apply(value);
+        }
+
+        for (output, slot) in outputs.iter_mut().zip(&self.output_slots) {
+            *output = self.values[*slot];
+        }
+    }
+}
+
+impl NeuralNetwork for CompiledNetwork {
+    fn num_outputs(&self) -> usize {
+        self.output_slots.len()
+    }
+
+    fn activate_into(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        CompiledNetwork::activate_into(self, inputs, outputs)
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Network that may contain cycles. Every neuron reads the values its inputs had on the
+/// previous activation, so information travelling around a loop is delayed by one step.
+#[derive(Debug, PartialEq)]
+pub struct RecurrentNeuralNetwork {
+    pub input_ids: Vec<i32>,
+    pub output_ids: Vec<i32>,
+    pub neurons: Vec<Neuron>,
+    values: HashMap<i32, f32>,
+}
+
+impl RecurrentNeuralNetwork {
+    pub fn activate(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+        assert!(inputs.len() == self.input_ids.len());
+
+        for (input_id, value) in self.input_ids.iter().zip(inputs) {
+            self.values.insert(*input_id, value);
+        }
+
+        let mut next_values = self.values.clone();
+        for neuron in &self.neurons {
+            let mut value = neuron.aggregation.aggregate(
+                neuron
+                    .inputs
+                    .iter()
+                    .map(|input| self.values.get(&input.input_id).copied().unwrap_or(0.0) * input.weight),
+            );
+            value += neuron.bias;
+            value = neuron.activation.apply(value);
+
+            next_values.insert(neuron.id, value);
+        }
+        self.values = next_values;
+
+        self.output_ids.iter().map(|id| self.values[id]).collect()
+    }
+
+    pub fn reset(&mut self) {
+        for value in self.values.values_mut() {
+            *value = 0.0;
+        }
+    }
+
+    pub fn create_from_genome(genome: &Genome) -> RecurrentNeuralNetwork {
+        let inputs = genome.make_input_ids();
+        let outputs = genome.make_output_ids();
+
+        let mut neurons: Vec<Neuron> = Vec::new();
+        for neuron_gene in genome.neurons.iter().filter(|n| !inputs.contains(&n.id)) {
+            let neuron_inputs: Vec<NeuronInput> = genome
+                .links
+                .iter()
+                .filter(|link| link.is_enabled && link.id.out_id == neuron_gene.id)
+                .map(|link| NeuronInput {
+                    input_id: link.id.in_id,
+                    weight: link.weight,
+                })
+                .collect();
+
+            neurons.push(Neuron {
+                id: neuron_gene.id,
+                bias: neuron_gene.bias,
+                activation: neuron_gene.activation,
+                aggregation: neuron_gene.aggregation,
+                inputs: neuron_inputs,
+            });
+        }
+
+        let mut values: HashMap<i32, f32> = HashMap::new();
+        for id in inputs.iter().chain(outputs.iter()).chain(neurons.iter().map(|n| &n.id)) {
+            values.insert(*id, 0.0);
+        }
+
+        RecurrentNeuralNetwork {
+            input_ids: inputs,
+            output_ids: outputs,
+            neurons,
+            values,
+        }
+    }
+}
+
+impl NeuralNetwork for RecurrentNeuralNetwork {
+    fn num_outputs(&self) -> usize {
+        self.output_ids.len()
+    }
+
+    fn activate_into(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+        outputs.copy_from_slice(&RecurrentNeuralNetwork::activate(self, inputs.to_vec()));
+    }
+
+    fn reset(&mut self) {
+        RecurrentNeuralNetwork::reset(self)
+    }
+}
+
+/// Build the phenotype selected by `Config::allow_recurrent`
+pub fn create_network(genome: &Genome) -> Box<dyn NeuralNetwork> {
+    let config = crate::config::Config::global();
+    if config.allow_recurrent {
+        Box::new(RecurrentNeuralNetwork::create_from_genome(genome))
+    } else {
+        Box::new(CompiledNetwork::create_from_genome(genome))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::neat::*;
+    use crate::neat::rng::derive_rng;
+    use crate::neat::test_utils::link;
+    use rand::Rng;
 
     #[test]
     fn simple_nn_activation_test() {
@@ -210,6 +465,8 @@ mod tests {
             Neuron {
                 id: 0,
                 bias: 0.,
+                activation: ActivationFunction::Identity,
+                aggregation: AggregationFunction::Sum,
                 inputs: vec![
                     NeuronInput {
                         input_id: -1,
@@ -228,6 +485,8 @@ mod tests {
             Neuron {
                 id: 1,
                 bias: 1.,
+                activation: ActivationFunction::Identity,
+                aggregation: AggregationFunction::Sum,
                 inputs: vec![
                     NeuronInput {
                         input_id: -1,
@@ -246,6 +505,8 @@ mod tests {
             Neuron {
                 id: 2,
                 bias: 2.,
+                activation: ActivationFunction::Identity,
+                aggregation: AggregationFunction::Sum,
                 inputs: vec![
                     NeuronInput {
                         input_id: -1,
@@ -281,6 +542,8 @@ mod tests {
             Neuron {
                 id: 0,
                 bias: 0.,
+                activation: ActivationFunction::Identity,
+                aggregation: AggregationFunction::Sum,
                 inputs: vec![
                     NeuronInput {
                         input_id: -1,
@@ -299,6 +562,8 @@ mod tests {
             Neuron {
                 id: 1,
                 bias: 1.,
+                activation: ActivationFunction::Identity,
+                aggregation: AggregationFunction::Sum,
                 inputs: vec![
                     NeuronInput {
                         input_id: -1,
@@ -317,6 +582,8 @@ mod tests {
             Neuron {
                 id: 2,
                 bias: 2.,
+                activation: ActivationFunction::Identity,
+                aggregation: AggregationFunction::Sum,
                 inputs: vec![
                     NeuronInput {
                         input_id: -1,
@@ -344,12 +611,12 @@ mod tests {
             num_inputs: 3,
             num_outputs: 3,
             neurons: vec![
-                NeuronGene { id: -1, bias: -1. },
-                NeuronGene { id: -2, bias: -2. },
-                NeuronGene { id: -3, bias: -3. },
-                NeuronGene { id: 0, bias: 0. },
-                NeuronGene { id: 1, bias: 1. },
-                NeuronGene { id: 2, bias: 2. },
+                NeuronGene { id: -1, bias: -1., activation: ActivationFunction::Identity, aggregation: AggregationFunction::Sum },
+                NeuronGene { id: -2, bias: -2., activation: ActivationFunction::Identity, aggregation: AggregationFunction::Sum },
+                NeuronGene { id: -3, bias: -3., activation: ActivationFunction::Identity, aggregation: AggregationFunction::Sum },
+                NeuronGene { id: 0, bias: 0., activation: ActivationFunction::Identity, aggregation: AggregationFunction::Sum },
+                NeuronGene { id: 1, bias: 1., activation: ActivationFunction::Identity, aggregation: AggregationFunction::Sum },
+                NeuronGene { id: 2, bias: 2., activation: ActivationFunction::Identity, aggregation: AggregationFunction::Sum },
             ],
             links: vec![
                 LinkGene {
@@ -359,6 +626,7 @@ mod tests {
                     },
                     weight: 1.,
                     is_enabled: true,
+                    innovation: 0,
                 },
                 LinkGene {
                     id: LinkID {
@@ -367,6 +635,7 @@ mod tests {
                     },
                     weight: 1.,
                     is_enabled: true,
+                    innovation: 1,
                 },
                 LinkGene {
                     id: LinkID {
@@ -375,6 +644,7 @@ mod tests {
                     },
                     weight: 1.,
                     is_enabled: true,
+                    innovation: 2,
                 },
                 LinkGene {
                     id: LinkID {
@@ -383,6 +653,7 @@ mod tests {
                     },
                     weight: 2.,
                     is_enabled: true,
+                    innovation: 3,
                 },
                 LinkGene {
                     id: LinkID {
@@ -391,6 +662,7 @@ mod tests {
                     },
                     weight: 2.,
                     is_enabled: true,
+                    innovation: 4,
                 },
                 LinkGene {
                     id: LinkID {
@@ -399,6 +671,7 @@ mod tests {
                     },
                     weight: 2.,
                     is_enabled: true,
+                    innovation: 5,
                 },
                 LinkGene {
                     id: LinkID {
@@ -407,6 +680,7 @@ mod tests {
                     },
                     weight: 3.,
                     is_enabled: true,
+                    innovation: 6,
                 },
                 LinkGene {
                     id: LinkID {
@@ -415,6 +689,7 @@ mod tests {
                     },
                     weight: 3.,
                     is_enabled: true,
+                    innovation: 7,
                 },
                 LinkGene {
                     id: LinkID {
@@ -423,6 +698,7 @@ mod tests {
                     },
                     weight: 3.,
                     is_enabled: true,
+                    innovation: 8,
                 },
             ],
         };
@@ -435,4 +711,147 @@ mod tests {
 
         assert_eq!(expected_nn, nn_from_genome);
     }
+
+    #[test]
+    fn recurrent_self_link_keeps_state() {
+        // -1 -> 1 (hidden) -> 0 (output), with a self link on the hidden neuron
+        let link = |in_id, out_id, weight, innovation| link(in_id, out_id, innovation, weight, true);
+        let genome = Genome {
+            id: 1,
+            num_inputs: 1,
+            num_outputs: 1,
+            neurons: vec![
+                NeuronGene { id: -1, bias: 0., activation: ActivationFunction::Identity, aggregation: AggregationFunction::Sum },
+                NeuronGene { id: 0, bias: 0., activation: ActivationFunction::Identity, aggregation: AggregationFunction::Sum },
+                NeuronGene { id: 1, bias: 0., activation: ActivationFunction::Relu, aggregation: AggregationFunction::Sum },
+            ],
+            links: vec![link(-1, 1, 1., 0), link(1, 1, 1., 1), link(1, 0, 1., 2)],
+        };
+
+        let mut nn = RecurrentNeuralNetwork::create_from_genome(&genome);
+
+        // The hidden neuron accumulates its input, the output lags one step behind it
+        assert_eq!(nn.activate(vec![1.]), vec![0.]);
+        assert_eq!(nn.activate(vec![1.]), vec![1.]);
+        assert_eq!(nn.activate(vec![1.]), vec![2.]);
+
+        nn.reset();
+        assert_eq!(nn.activate(vec![1.]), vec![0.]);
+    }
+
+    // Random feed forward genome grown with the regular mutation operators
+    fn random_genome(num_mutations: usize, rng: &mut impl Rng) -> Genome {
+        use crate::neat::innovation::InnovationTracker;
+        use crate::neat::mutation::*;
+
+        let innovations = InnovationTracker::new(3, 3);
+        let mut genome = Genome::new(0, 3, 3, rng);
+        for _ in 0..num_mutations {
+            mutate_add_neuron(&mut genome, &innovations, rng);
+            mutate_add_link(&mut genome, &innovations, rng);
+            mutate_weights(&mut genome, rng);
+            mutate_biases(&mut genome, rng);
+            mutate_activations(&mut genome, rng);
+            mutate_aggregations(&mut genome, rng);
+            innovations.new_generation();
+        }
+        genome
+    }
+
+    #[test]
+    fn compiled_network_matches_feed_forward() {
+        let rng = &mut derive_rng(5, &[]);
+        for _ in 0..50 {
+            let genome = random_genome(20, rng);
+            let mut nn = FeedForwardNeuralNetwork::create_from_genome(&genome);
+            let mut compiled = CompiledNetwork::create_from_genome(&genome);
+
+            let mut outputs = vec![0.0; 3];
+            for _ in 0..10 {
+                let inputs: Vec<f32> = (0..3).map(|_| rng.random::<f32>()).collect();
+                compiled.activate_into(&inputs, &mut outputs);
+                let expected = nn.activate(inputs);
+                // Bit for bit, NaN included
+                let bits = |v: &[f32]| v.iter().map(|x| x.to_bits()).collect::<Vec<u32>>();
+                assert_eq!(bits(&expected), bits(&outputs));
+            }
+        }
+    }
+
+    // The original implementations, kept as a reference for the adjacency based ones
+    fn naive_required_for_output(inputs: &[i32], outputs: &[i32], links: &[LinkGene]) -> HashSet<i32> {
+        let mut required: HashSet<i32> = outputs.iter().copied().collect();
+        let mut s = required.clone();
+        loop {
+            let t: HashSet<i32> = links
+                .iter()
+                .filter(|x| s.contains(&x.id.out_id) && !s.contains(&x.id.in_id))
+                .map(|x| x.id.in_id)
+                .collect();
+            if t.is_empty() {
+                break;
+            }
+            let layer_nodes: HashSet<i32> = t.iter().filter(|x| !inputs.contains(x)).copied().collect();
+            if layer_nodes.is_empty() {
+                break;
+            }
+            required = &required | &layer_nodes;
+            s = &s | &t;
+        }
+        required
+    }
+
+    fn naive_feed_forward_layers(inputs: &[i32], outputs: &[i32], links: &[LinkGene]) -> Vec<HashSet<i32>> {
+        let required = naive_required_for_output(inputs, outputs, links);
+        let mut layers = Vec::new();
+        let mut s: HashSet<i32> = inputs.iter().copied().collect();
+        loop {
+            let c: HashSet<i32> = links
+                .iter()
+                .filter(|x| s.contains(&x.id.in_id) && !s.contains(&x.id.out_id))
+                .map(|x| x.id.out_id)
+                .collect();
+            let next_layer: HashSet<i32> = c
+                .into_iter()
+                .filter(|n| {
+                    required.contains(n)
+                        && links
+                            .iter()
+                            .filter(|x| x.id.out_id == *n && required.contains(&x.id.in_id))
+                            .all(|x| s.contains(&x.id.in_id))
+                })
+                .collect();
+            if next_layer.is_empty() {
+                break;
+            }
+            s = &s | &next_layer;
+            layers.push(next_layer);
+        }
+        layers
+    }
+
+    #[test]
+    fn feed_forward_layers_match_naive_implementation() {
+        let rng = &mut derive_rng(6, &[]);
+        for _ in 0..100 {
+            let mut genome = random_genome(30, rng);
+            // Disconnect some neurons so not everything is required
+            genome.links.retain(|_| rng.random::<f32>() < 0.8);
+
+            let inputs = genome.make_input_ids();
+            let outputs = genome.make_output_ids();
+
+            assert_eq!(
+                required_for_output(&inputs, &outputs, &genome.links),
+                naive_required_for_output(&inputs, &outputs, &genome.links)
+            );
+
+            let layers: Vec<HashSet<i32>> = feed_forward_layers(&inputs, &outputs, &genome.links)
+                .into_iter()
+                .map(|layer| layer.into_iter().collect())
+                .collect();
+            assert_eq!(layers, naive_feed_forward_layers(&inputs, &outputs, &genome.links));
+        }
+    }
 }
+
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use super::Individual;
+use super::selection::Objective;
+
+/// Behaviors that were novel when they were seen. New behaviors are compared against the
+/// archive as well as the current population, so the search keeps moving away from where it
+/// has already been.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NoveltyArchive {
+    pub behaviors: Vec<Vec<f32>>,
+}
+
+fn behavior_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+/// Rescale values to [0, 1]. All values are 0 if they are all equal.
+fn normalize(values: &[f32]) -> Vec<f32> {
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    values
+        .iter()
+        .map(|v| if max > min { (v - min) / (max - min) } else { 0.0 })
+        .collect()
+}
+
+impl NoveltyArchive {
+    /// Novelty of every behavior: the mean distance to its `k` nearest neighbors among the other
+    /// behaviors and the archive
+    pub fn novelty(&self, behaviors: &[Vec<f32>], k: usize) -> Vec<f32> {
+        behaviors
+            .iter()
+            .enumerate()
+            .map(|(i, behavior)| {
+                let mut distances: Vec<f32> = behaviors
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| other)
+                    .chain(&self.behaviors)
+                    .map(|other| behavior_distance(behavior, other))
+                    .collect();
+                distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+                let nearest = &distances[..k.min(distances.len())];
+                if nearest.is_empty() {
+                    0.0
+                } else {
+                    nearest.iter().sum::<f32>() / nearest.len() as f32
+                }
+            })
+            .collect()
+    }
+
+    /// Archive the `count` most novel behaviors, dropping the oldest ones past `max_size`
+    pub fn add_most_novel(&mut self, behaviors: &[Vec<f32>], novelty: &[f32], count: usize, max_size: usize) {
+        let mut ranking: Vec<usize> = (0..behaviors.len()).collect();
+        ranking.sort_by(|&a, &b| novelty[b].partial_cmp(&novelty[a]).unwrap());
+        self.behaviors.extend(ranking.iter().take(count).map(|&i| behaviors[i].clone()));
+
+        if self.behaviors.len() > max_size {
+            let excess = self.behaviors.len() - max_size;
+            self.behaviors.drain(..excess);
+        }
+    }
+}
+
+/// Replace every individual's fitness with its score under `objective`, updating the archive.
+/// `behaviors` belong to the individuals in the same order. Returns the novelty scores.
+pub fn apply_objective(
+    objective: Objective,
+    individuals: &mut [Individual],
+    behaviors: &[Vec<f32>],
+    archive: &mut NoveltyArchive,
+) -> Vec<f32> {
+    let config = crate::config::Config::global();
+
+    let novelty = archive.novelty(behaviors, config.novelty_k);
+    archive.add_most_novel(behaviors, &novelty, config.novelty_archive_per_generation, config.novelty_archive_size);
+
+    let scores = match objective {
+        // Not novelty based, fitness is left alone
+        Objective::Fitness | Objective::Pareto => return novelty,
+        Objective::Novelty => novelty.clone(),
+        Objective::Blend => {
+            let fitness: Vec<f32> = individuals.iter().map(|i| i.fitness).collect();
+            normalize(&fitness)
+                .iter()
+                .zip(normalize(&novelty))
+                .map(|(f, n)| (1.0 - config.novelty_weight) * f + config.novelty_weight * n)
+                .collect()
+        }
+    };
+
+    for (individual, score) in individuals.iter_mut().zip(scores) {
+        individual.fitness = score;
+    }
+    novelty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn novelty_is_mean_distance_to_nearest_neighbors() {
+        let mut archive = NoveltyArchive::default();
+        let behaviors = vec![vec![0.0], vec![1.0], vec![3.0], vec![10.0]];
+
+        let novelty = archive.novelty(&behaviors, 2);
+        assert_eq!(novelty, vec![2.0, 1.5, 2.5, 8.0]);
+
+        // The outlier is archived, and from then on counts as a neighbor of everything
+        archive.add_most_novel(&behaviors, &novelty, 1, 10);
+        assert_eq!(archive.behaviors, vec![vec![10.0]]);
+        assert_eq!(archive.novelty(&behaviors[..1], 2), vec![10.0]);
+
+        archive.add_most_novel(&behaviors, &novelty, 2, 2);
+        assert_eq!(archive.behaviors, vec![vec![10.0], vec![3.0]]);
+    }
+}
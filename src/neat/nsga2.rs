@@ -0,0 +1,169 @@
+//! Multi-objective selection from NSGA-II (Deb et al. 2002). Every objective is maximized, negate
+//! the ones that should be minimized.
+
+use std::cmp::Ordering;
+
+use rand::Rng;
+
+/// True if `a` is at least as good as `b` in every objective and better in at least one
+pub fn dominates(a: &[f32], b: &[f32]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x >= y) && a.iter().zip(b).any(|(x, y)| x > y)
+}
+
+/// Fast non-dominated sort: the first front holds every non-dominated solution, the second
+/// front those only dominated by the first, and so on. Fronts hold indices into `objectives`.
+pub fn non_dominated_fronts(objectives: &[Vec<f32>]) -> Vec<Vec<usize>> {
+    let n = objectives.len();
+    let mut dominated_by: Vec<usize> = vec![0; n];
+    let mut dominating: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for a in 0..n {
+        for b in (a + 1)..n {
+            if dominates(&objectives[a], &objectives[b]) {
+                dominating[a].push(b);
+                dominated_by[b] += 1;
+            } else if dominates(&objectives[b], &objectives[a]) {
+                dominating[b].push(a);
+                dominated_by[a] += 1;
+            }
+        }
+    }
+
+    let mut fronts: Vec<Vec<usize>> = Vec::new();
+    let mut front: Vec<usize> = (0..n).filter(|&i| dominated_by[i] == 0).collect();
+    while !front.is_empty() {
+        let mut next_front = Vec::new();
+        for &a in &front {
+            for &b in &dominating[a] {
+                dominated_by[b] -= 1;
+                if dominated_by[b] == 0 {
+                    next_front.push(b);
+                }
+            }
+        }
+        next_front.sort();
+        fronts.push(front);
+        front = next_front;
+    }
+    fronts
+}
+
+/// Crowding distance of every member of `front`: how much room there is around it along each
+/// objective. The extremes of every objective get an infinite distance so they are always kept.
+pub fn crowding_distances(objectives: &[Vec<f32>], front: &[usize]) -> Vec<f32> {
+    let mut distances = vec![0.0; front.len()];
+    let num_objectives = objectives.first().map_or(0, |o| o.len());
+    let columns = (0..num_objectives).map(|m| front.iter().map(|&i| objectives[i][m]).collect::<Vec<f32>>());
+
+    for values in columns {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap());
+
+        let (first, last) = (order[0], order[order.len() - 1]);
+        distances[first] = f32::INFINITY;
+        distances[last] = f32::INFINITY;
+        let range = values[last] - values[first];
+        if range == 0.0 {
+            continue;
+        }
+        for w in order.windows(3) {
+            distances[w[1]] += (values[w[2]] - values[w[0]]) / range;
+        }
+    }
+    distances
+}
+
+/// Where a solution stands after non-dominated sorting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rank {
+    pub front: usize,
+    pub crowding: f32,
+}
+
+/// Crowded comparison: the better front comes first, within a front the less crowded solution
+pub fn crowded_cmp(a: &Rank, b: &Rank) -> Ordering {
+    a.front.cmp(&b.front).then(b.crowding.partial_cmp(&a.crowding).unwrap())
+}
+
+/// Environmental selection over parents and offspring together: whole fronts survive while
+/// they fit in `size`, the front that doesn't fit is cut down to its least crowded members.
+/// Returns the survivors' indices into `objectives` with their ranks.
+pub fn select_survivors(objectives: &[Vec<f32>], size: usize) -> Vec<(usize, Rank)> {
+    let mut survivors: Vec<(usize, Rank)> = Vec::new();
+    for (front_index, front) in non_dominated_fronts(objectives).iter().enumerate() {
+        if survivors.len() >= size {
+            break;
+        }
+        let mut members: Vec<(usize, Rank)> = front
+            .iter()
+            .zip(crowding_distances(objectives, front))
+            .map(|(&i, crowding)| (i, Rank { front: front_index, crowding }))
+            .collect();
+        members.sort_by(|a, b| crowded_cmp(&a.1, &b.1));
+        survivors.extend(members.into_iter().take(size - survivors.len()));
+    }
+    survivors
+}
+
+/// Binary tournament: the better of two random solutions by crowded comparison
+pub fn tournament(ranks: &[Rank], rng: &mut impl Rng) -> usize {
+    let a = rng.random_range(0..ranks.len());
+    let b = rng.random_range(0..ranks.len());
+    if crowded_cmp(&ranks[b], &ranks[a]) == Ordering::Less { b } else { a }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fronts_and_crowding() {
+        let objectives = vec![
+            vec![1.0, 4.0],
+            vec![2.0, 3.0],
+            vec![3.0, 1.0],
+            vec![1.0, 2.0], // dominated by 1
+            vec![0.0, 0.0], // dominated by everything
+            vec![4.0, 0.0],
+        ];
+
+        assert_eq!(non_dominated_fronts(&objectives), vec![vec![0, 1, 2, 5], vec![3], vec![4]]);
+
+        // Extremes are infinitely far, 1 and 2 sit between their neighbors
+        let distances = crowding_distances(&objectives, &[0, 1, 2, 5]);
+        assert!(distances[0].is_infinite() && distances[3].is_infinite());
+        assert!((distances[1] - (2.0 / 3.0 + 3.0 / 4.0)).abs() < 1e-6);
+        assert!((distances[2] - (2.0 / 3.0 + 3.0 / 4.0)).abs() < 1e-6);
+
+        // A better front beats any crowding, within a front less crowded wins
+        let ranks = [Rank { front: 0, crowding: 0.5 }, Rank { front: 1, crowding: f32::INFINITY }];
+        assert_eq!(crowded_cmp(&ranks[0], &ranks[1]), Ordering::Less);
+        assert_eq!(crowded_cmp(&Rank { front: 0, crowding: 2.0 }, &ranks[0]), Ordering::Less);
+
+        // Front 1 only wins when it's drawn twice
+        let mut rng = crate::neat::rng::derive_rng(21, &[]);
+        let wins = (0..1000).filter(|_| tournament(&ranks, &mut rng) == 0).count();
+        assert!((700..800).contains(&wins), "{}", wins);
+    }
+
+    #[test]
+    fn front_zero_always_survives() {
+        let mut rng = crate::neat::rng::derive_rng(21, &[]);
+        for size in 1..12 {
+            // A non-dominated line plus dominated points, front 0 always fits
+            let mut objectives: Vec<Vec<f32>> = (0..size).map(|i| vec![i as f32, (size - i) as f32]).collect();
+            objectives.extend((0..20).map(|_| vec![rng.random_range(-5.0..0.0), rng.random_range(-5.0..0.0)]));
+
+            let survivors = select_survivors(&objectives, size + 3);
+            assert_eq!(survivors.len(), size + 3);
+            for i in 0..size {
+                assert!(survivors.iter().any(|&(s, rank)| s == i && rank.front == 0), "{} died", i);
+            }
+        }
+
+        // The last front is cut by crowding, the extremes stay
+        let objectives = vec![vec![0.0, 3.0], vec![1.0, 2.0], vec![1.1, 1.9], vec![3.0, 0.0]];
+        let kept: Vec<usize> = select_survivors(&objectives, 3).iter().map(|&(i, _)| i).collect();
+        assert_eq!(kept.len(), 3);
+        assert!(kept.contains(&0) && kept.contains(&3));
+    }
+}
@@ -1,6 +1,13 @@
-use rand::seq::IndexedRandom;
+use rayon::prelude::*;
+
 use super::crossover::crossover;
 use super::mutation::mutate;
+use super::GenomeIdAllocator;
+use super::innovation::InnovationTracker;
+use super::nsga2;
+use super::rng::{self, derive_rng};
+use super::selection::{self, SelectionStrategy};
+use super::species::{Species, StagnationEvent};
 
 pub fn sort_individuals_by_fitness(individuals: &mut Vec<crate::neat::Individual>) {
     individuals.sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap());
@@ -9,61 +16,322 @@ pub fn sort_individuals_by_fitness(individuals: &mut Vec<crate::neat::Individual
 use crate::neat::Individual;
 pub struct Population {
     pub individuals: Vec<Individual>,
-    pub best: Individual
+    pub best: Individual,
+    pub species: Vec<Species>,
+    pub innovations: InnovationTracker,
+    pub genome_ids: GenomeIdAllocator,
+    pub generation: usize,
+    // Master seed, every random decision of the run is derived from it
+    pub seed: u64,
+    pub selection: Box<dyn SelectionStrategy>,
+    // Species removed for stagnating during the last call to `reproduce`
+    pub stagnated: Vec<StagnationEvent>,
+    pub next_species_id: i32,
 }
 
 impl Population {
-    pub fn new() -> Self {
+    pub fn new(seed: u64) -> Self {
         let config = crate::config::Config::global();
+        let genome_ids = GenomeIdAllocator::new(1);
+        let (num_inputs, num_outputs) = config.genome_shape();
         Self {
             individuals: Vec::new(),
             best: Individual {
-                genome: crate::neat::Genome::new(config.num_inputs, config.num_outputs),
+                genome: crate::neat::Genome::new(genome_ids.next_id(), num_inputs, num_outputs, &mut derive_rng(seed, &[rng::INIT])),
                 fitness: 0.0,
-            }
+            },
+            species: Vec::new(),
+            innovations: InnovationTracker::new(num_inputs, num_outputs),
+            genome_ids,
+            generation: 0,
+            seed,
+            selection: selection::from_config(),
+            stagnated: Vec::new(),
+            next_species_id: 0,
         }
     }
 
     pub fn populate(&mut self) {
         let config = crate::config::Config::global();
+        let mut rng = derive_rng(self.seed, &[rng::INIT]);
+        let (num_inputs, num_outputs) = config.genome_shape();
 
         for _ in 0..config.population_size {
-            self.individuals.push(Individual{ genome: super::Genome::new(config.num_inputs, config.num_outputs), fitness: 0.0})
+            self.individuals.push(Individual{ genome: super::Genome::new(self.genome_ids.next_id(), num_inputs, num_outputs, &mut rng), fitness: 0.0})
         }
     }
 
-    pub fn populate_from_genome(&mut self, prev_genome: super::Genome) {
+    pub fn populate_from_genome(&mut self, mut prev_genome: super::Genome) {
         let config = crate::config::Config::global();
 
+        self.innovations.register_genome(&mut prev_genome);
+        self.genome_ids.skip_past(prev_genome.id);
+
+        // Copies of the genome, each with an ID of its own
         for _ in 0..config.population_size {
-            self.individuals.push(Individual{ genome: prev_genome.clone(), fitness: 0.0})
+            let genome = super::Genome { id: self.genome_ids.next_id(), ..prev_genome.clone() };
+            self.individuals.push(Individual{ genome, fitness: 0.0})
         }
     }
 
-    pub fn reproduce(&mut self) -> Vec<Individual> {
+    /// Group the current individuals into species. Each existing species first picks the
+    /// individual closest to its old representative as its new representative, then every
+    /// remaining individual joins the first species it is compatible with, or founds a new one.
+    pub fn speciate(&mut self) {
         let config = crate::config::Config::global();
 
-        let reproduction_cutoff = (config.survival_threshold * self.individuals.len() as f32).ceil() as usize;
+        let mut unspeciated: Vec<usize> = (0..self.individuals.len()).collect();
+        for species in self.species.iter_mut() {
+            species.members.clear();
+
+            let closest = unspeciated
+                .iter()
+                .enumerate()
+                .map(|(pos, &i)| (pos, i, species.representative.distance(&self.individuals[i].genome, config)))
+                .filter(|(_, _, distance)| *distance < config.compatibility_threshold)
+                .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+
+            if let Some((pos, i, _)) = closest {
+                species.representative = self.individuals[i].genome.clone();
+                species.members.push(i);
+                unspeciated.remove(pos);
+            }
+        }
+
+        // Species that didn't find a new representative went extinct
+        self.species.retain(|s| !s.members.is_empty());
 
-        // Individuals are sorted with BEST first, so take from the front, not the back!
-        let survived_members: Vec<Individual> = self.individuals[..reproduction_cutoff].to_vec();
+        for i in unspeciated {
+            let genome = &self.individuals[i].genome;
+            if let Some(species) = self
+                .species
+                .iter_mut()
+                .find(|s| s.representative.distance(genome, config) < config.compatibility_threshold)
+            {
+                species.members.push(i);
+            } else {
+                let mut species = Species::new(self.next_species_id, genome.clone(), self.generation);
+                species.members.push(i);
+                self.species.push(species);
+                self.next_species_id += 1;
+            }
+        }
+
+        // Keep members ordered best first
+        for species in self.species.iter_mut() {
+            species.members.sort_by(|a, b| {
+                self.individuals[*b].fitness.partial_cmp(&self.individuals[*a].fitness).unwrap()
+            });
+        }
+    }
+
+    /// Record every species' best fitness, then drop the species that haven't improved for
+    /// `max_stagnation` generations. The `species_elitism` best species are never dropped, and
+    /// if every species stagnated the best one is kept so the population can't die out.
+    fn remove_stagnant_species(&mut self) {
+        let config = crate::config::Config::global();
+
+        for species in self.species.iter_mut() {
+            // Members are sorted best first
+            let best = self.individuals[species.members[0]].fitness;
+            if best > species.best_fitness {
+                species.best_fitness = best;
+                species.last_improved = self.generation;
+            }
+        }
+
+        let mut ranking: Vec<usize> = (0..self.species.len()).collect();
+        ranking.sort_by(|&a, &b| self.species[b].best_fitness.partial_cmp(&self.species[a].best_fitness).unwrap());
+        let protected: Vec<i32> = ranking
+            .iter()
+            .take(config.species_elitism)
+            .map(|&i| self.species[i].id)
+            .collect();
+
+        let generation = self.generation;
+        let (mut stagnated, mut alive): (Vec<Species>, Vec<Species>) = self.species.drain(..).partition(|s| {
+            !protected.contains(&s.id) && s.generations_without_improvement(generation) >= config.max_stagnation
+        });
+        if alive.is_empty() && !stagnated.is_empty() {
+            let best = (0..stagnated.len())
+                .max_by(|&a, &b| stagnated[a].best_fitness.partial_cmp(&stagnated[b].best_fitness).unwrap())
+                .unwrap();
+            alive.push(stagnated.remove(best));
+        }
+        self.species = alive;
+        self.stagnated = stagnated
+            .iter()
+            .map(|s| StagnationEvent {
+                species_id: s.id,
+                generations_without_improvement: s.generations_without_improvement(generation),
+                best_fitness: s.best_fitness,
+            })
+            .collect();
+    }
+
+    /// Explicit fitness sharing: every individual's fitness is divided by the size of its species,
+    /// and each species gets offspring proportional to the sum of its members' adjusted fitness.
+    /// Fitness is shifted by the population minimum first so adjusted fitness is never negative.
+    /// The counts always add up to `spawn_size`, species left with none die out.
+    fn offspring_counts(&mut self, spawn_size: usize) -> Vec<usize> {
+        let config = crate::config::Config::global();
+
+        let min_fitness = self.individuals.iter().map(|i| i.fitness).fold(f32::INFINITY, f32::min);
+        for species in self.species.iter_mut() {
+            let species_size = species.members.len() as f32;
+            species.adjusted_fitness = species
+                .members
+                .iter()
+                .map(|&i| (self.individuals[i].fitness - min_fitness) / species_size)
+                .sum();
+        }
+
+        let total: f32 = self.species.iter().map(|s| s.adjusted_fitness).sum();
+        let shares: Vec<f32> = self
+            .species
+            .iter()
+            .map(|s| {
+                if total > 0.0 {
+                    s.adjusted_fitness / total * spawn_size as f32
+                } else {
+                    spawn_size as f32 / self.species.len() as f32
+                }
+            })
+            .collect();
+
+        // Every species is promised `min_species_size` offspring as long as that fits in
+        // `spawn_size`, otherwise only the species with the largest shares are
+        let mut ranking: Vec<usize> = (0..shares.len()).collect();
+        ranking.sort_by(|&a, &b| shares[b].partial_cmp(&shares[a]).unwrap());
+        let num_promised = spawn_size.checked_div(config.min_species_size).unwrap_or(shares.len());
+        let mut minimums = vec![0; shares.len()];
+        for &i in ranking.iter().take(num_promised) {
+            minimums[i] = config.min_species_size;
+        }
+
+        let mut counts: Vec<usize> = shares
+            .iter()
+            .zip(&minimums)
+            .map(|(share, &minimum)| (share.floor() as usize).max(minimum))
+            .collect();
+
+        // Hand out the slots lost to rounding to the species that were rounded down the most
+        while counts.iter().sum::<usize>() < spawn_size {
+            let i = (0..counts.len())
+                .max_by(|&a, &b| (shares[a] - counts[a] as f32).partial_cmp(&(shares[b] - counts[b] as f32)).unwrap())
+                .unwrap();
+            counts[i] += 1;
+        }
+
+        // The minimums can push us over, take the excess from the largest species above its
+        // minimum. The minimums add up to at most `spawn_size`, so there always is one.
+        while counts.iter().sum::<usize>() > spawn_size {
+            let largest = (0..counts.len())
+                .filter(|&i| counts[i] > minimums[i])
+                .max_by_key(|&i| counts[i])
+                .unwrap();
+            counts[largest] -= 1;
+        }
+
+        counts
+    }
+
+    pub fn reproduce(&mut self) -> Vec<Individual> {
+        let config = crate::config::Config::global();
+
+        self.speciate();
+        self.remove_stagnant_species();
+        self.innovations.new_generation();
 
         let mut new_gen:Vec<Individual> = Vec::new();
-        let spawn_size = config.population_size;
 
-        let mut rng = rand::rng();
+        // Global elites are copied verbatim (keeping their genome IDs) before anything else
+        let mut ranking: Vec<usize> = (0..self.individuals.len()).collect();
+        ranking.sort_by(|&a, &b| self.individuals[b].fitness.partial_cmp(&self.individuals[a].fitness).unwrap());
+        let elites: Vec<usize> = ranking.into_iter().take(config.elitism.min(config.population_size)).collect();
+        for &i in &elites {
+            new_gen.push(Individual{genome: self.individuals[i].genome.clone(), fitness: 0.0});
+        }
+
+        let spawn_size = config.population_size - elites.len();
+        let offspring_counts = self.offspring_counts(spawn_size);
 
-        for _ in 0..spawn_size {
-            let p1 = survived_members.choose(&mut rng).unwrap();
-            let p2 = survived_members.choose(&mut rng).unwrap();
-            let mut offspring = crossover(p1, p2);
-            mutate(&mut offspring);
-            new_gen.push(Individual{genome: offspring, fitness: 0.0});
+        // Plan the offspring on this thread: which species each one comes from and its genome ID
+        let mut plans: Vec<(usize, i32)> = Vec::new();
+        for (s, (species, num_offspring)) in self.species.iter().zip(offspring_counts).enumerate() {
+            // Species elites count towards the species' offspring
+            let species_elites: Vec<usize> = species
+                .members
+                .iter()
+                .copied()
+                .filter(|i| !elites.contains(i))
+                .take(config.elitism_per_species.min(num_offspring))
+                .collect();
+            for &i in &species_elites {
+                new_gen.push(Individual{genome: self.individuals[i].genome.clone(), fitness: 0.0});
+            }
+
+            for _ in 0..(num_offspring - species_elites.len()) {
+                plans.push((s, self.genome_ids.next_id()));
+            }
         }
 
+        // Members are sorted with BEST first
+        let members: Vec<Vec<&Individual>> = self
+            .species
+            .iter()
+            .map(|species| species.members.iter().map(|&i| &self.individuals[i]).collect())
+            .collect();
+
+        // Every offspring has its own RNG stream, so it doesn't matter which thread builds it
+        let mark = self.innovations.mark();
+        let mut offspring: Vec<Individual> = plans
+            .par_iter()
+            .enumerate()
+            .map(|(index, &(s, id))| {
+                let mut rng = derive_rng(self.seed, &[rng::REPRODUCE, self.generation as u64, index as u64]);
+                let p1 = self.selection.select(&members[s], &mut rng);
+                let p2 = self.selection.select(&members[s], &mut rng);
+                let mut genome = crossover(p1, p2, id, &mut rng);
+                mutate(&mut genome, &self.innovations, &mut rng);
+                Individual{genome, fitness: 0.0}
+            })
+            .collect();
+        self.innovations.renumber_since(mark, offspring.iter_mut().map(|i| &mut i.genome));
+        new_gen.extend(offspring);
+
+        self.generation += 1;
         new_gen
     }
 
+    /// One NSGA-II generation: every offspring comes from two parents picked by binary
+    /// tournaments on `ranks` (see `nsga2`), without speciation or elitism. Parents compete with
+    /// their offspring for survival in `nsga2::select_survivors`.
+    pub fn reproduce_nsga2(&mut self, parents: &[Individual], ranks: &[nsga2::Rank]) -> Vec<Individual> {
+        let config = crate::config::Config::global();
+
+        self.innovations.new_generation();
+        let ids: Vec<i32> = (0..config.population_size).map(|_| self.genome_ids.next_id()).collect();
+
+        let mark = self.innovations.mark();
+        let mut offspring: Vec<Individual> = ids
+            .par_iter()
+            .enumerate()
+            .map(|(index, &id)| {
+                let mut rng = derive_rng(self.seed, &[rng::REPRODUCE, self.generation as u64, index as u64]);
+                let p1 = &parents[nsga2::tournament(ranks, &mut rng)];
+                let p2 = &parents[nsga2::tournament(ranks, &mut rng)];
+                let mut genome = crossover(p1, p2, id, &mut rng);
+                mutate(&mut genome, &self.innovations, &mut rng);
+                Individual{genome, fitness: 0.0}
+            })
+            .collect();
+        self.innovations.renumber_since(mark, offspring.iter_mut().map(|i| &mut i.genome));
+
+        self.generation += 1;
+        offspring
+    }
+
     pub fn run(&mut self, compute_fitness: fn(&mut Vec<Individual>), num_generations: i32) {
         for _ in 0..num_generations {
             compute_fitness(&mut self.individuals);
@@ -73,3 +341,219 @@ impl Population {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neat::Genome;
+
+    // Copy of `genome` with every weight shifted by `offset`
+    fn shifted(genome: &Genome, id: i32, offset: f32) -> Individual {
+        let mut genome = Genome { id, ..genome.clone() };
+        for link in genome.links.iter_mut() {
+            link.weight += offset;
+        }
+        Individual { genome, fitness: 0.0 }
+    }
+
+    // Population with one species per entry of `fitness`, holding individuals of those fitnesses
+    fn with_species(fitness: &[&[f32]]) -> Population {
+        let base = Genome::new(1, 3, 3, &mut derive_rng(9, &[rng::INIT]));
+        let mut population = Population::new(9);
+        for (id, species_fitness) in fitness.iter().enumerate() {
+            let mut species = Species::new(id as i32, base.clone(), 0);
+            for &fitness in species_fitness.iter() {
+                species.members.push(population.individuals.len());
+                population.individuals.push(Individual { genome: base.clone(), fitness });
+            }
+            population.species.push(species);
+        }
+        population
+    }
+
+    #[test]
+    fn speciation_by_compatibility_threshold() {
+        let config = crate::config::Config::global();
+        let base = Genome::new(1, 3, 3, &mut derive_rng(9, &[rng::INIT]));
+        // Weight differences alone put these past the threshold
+        let far = config.compatibility_threshold / config.c3_weight + 1.0;
+
+        let mut population = Population::new(9);
+        population.individuals = vec![shifted(&base, 1, 0.0), shifted(&base, 2, far), shifted(&base, 3, 0.1)];
+        population.speciate();
+
+        // 1 founds a species, 2 is too far and founds another, 3 is close to 1
+        assert_eq!(population.species.len(), 2);
+        assert_eq!(population.species[0].members, vec![0, 2]);
+        assert_eq!(population.species[1].members, vec![1]);
+        assert_eq!(population.species[0].representative.id, 1);
+
+        // The new representative is the individual closest to the old one
+        population.individuals = vec![shifted(&base, 4, far), shifted(&base, 5, 0.5), shifted(&base, 6, 0.2)];
+        population.speciate();
+        assert_eq!(population.species[0].representative.id, 6);
+        assert_eq!(population.species[1].representative.id, 4);
+        // Representatives join first
+        assert_eq!(population.species[0].members, vec![2, 1]);
+        assert_eq!(population.species[1].members, vec![0]);
+    }
+
+    #[test]
+    fn reproduction_does_not_depend_on_thread_count() {
+        let run = |num_threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build().unwrap();
+            pool.install(|| {
+                let mut population = Population::new(3);
+                population.populate();
+                for _ in 0..5 {
+                    for (i, individual) in population.individuals.iter_mut().enumerate() {
+                        individual.fitness = (individual.genome.links.len() + i % 5) as f32;
+                    }
+                    population.individuals = population.reproduce();
+                }
+                let genomes: Vec<&crate::neat::Genome> = population.individuals.iter().map(|i| &i.genome).collect();
+                format!("{:?}", genomes)
+            })
+        };
+
+        assert_eq!(run(1), run(4));
+    }
+
+    #[test]
+    fn offspring_proportional_to_adjusted_fitness() {
+        // Shifted by the minimum of 1: the first species adjusts to (1 + 3) / 2 = 2, the second
+        // to (0 + 6 + 12) / 3 = 6
+        let mut population = with_species(&[&[2.0, 4.0], &[1.0, 7.0, 13.0]]);
+        let counts = population.offspring_counts(16);
+        assert_eq!(population.species[0].adjusted_fitness, 2.0);
+        assert_eq!(population.species[1].adjusted_fitness, 6.0);
+        assert_eq!(counts, vec![4, 12]);
+    }
+
+    #[test]
+    fn offspring_counts_add_up_to_spawn_size() {
+        let config = crate::config::Config::global();
+        let fitness: Vec<Vec<f32>> = (0..10).map(|s| (0..=s).map(|i| (i * s) as f32).collect()).collect();
+        let fitness: Vec<&[f32]> = fitness.iter().map(|f| &f[..]).collect();
+        let mut population = with_species(&fitness);
+
+        for spawn_size in 0..60 {
+            let counts = population.offspring_counts(spawn_size);
+            assert_eq!(counts.iter().sum::<usize>(), spawn_size, "{:?}", counts);
+            // As many species as fit get the minimum
+            let promised = (spawn_size / config.min_species_size).min(counts.len());
+            assert!(counts.iter().filter(|&&c| c >= config.min_species_size).count() >= promised, "{:?}", counts);
+        }
+
+        // Too small for every species to get the minimum, the worst ones get nothing
+        let counts = population.offspring_counts(5);
+        assert_eq!(counts[0], 0);
+        assert!(counts[9] >= config.min_species_size);
+    }
+
+    #[test]
+    fn stagnant_species_are_dropped_except_the_best() {
+        let config = crate::config::Config::global();
+        let mut population = with_species(&[&[1.0], &[2.0], &[3.0], &[4.0], &[5.0]]);
+        population.generation = config.max_stagnation;
+        // None of them beat their record, species 4 improved a generation ago
+        for (i, species) in population.species.iter_mut().enumerate() {
+            species.best_fitness = 10.0 + i as f32;
+        }
+        population.species[4].last_improved = config.max_stagnation - 1;
+        population.species[4].best_fitness = 0.0;
+
+        population.remove_stagnant_species();
+
+        // The `species_elitism` species with the best records survive stagnation
+        let mut expected: Vec<i32> = (0..4).rev().take(config.species_elitism).collect();
+        expected.push(4);
+        expected.sort();
+        let alive: Vec<i32> = population.species.iter().map(|s| s.id).collect();
+        assert_eq!(alive, expected);
+        let mut dropped: Vec<i32> = population.stagnated.iter().map(|e| e.species_id).collect();
+        dropped.sort();
+        assert_eq!(dropped, (0..4 - config.species_elitism as i32).collect::<Vec<i32>>());
+        assert!(population.stagnated.iter().all(|e| e.generations_without_improvement == config.max_stagnation));
+
+        // Species 4 set a new record and starts counting again
+        assert_eq!(population.species.last().unwrap().best_fitness, 5.0);
+        assert_eq!(population.species.last().unwrap().last_improved, config.max_stagnation);
+    }
+
+    #[test]
+    fn elites_carry_over_unchanged() {
+        let config = crate::config::Config::global();
+        let base = Genome::new(1, 3, 3, &mut derive_rng(9, &[rng::INIT]));
+        let far = config.compatibility_threshold / config.c3_weight + 1.0;
+
+        // Five species of 30, fitness rising with the ID
+        let mut population = Population::new(9);
+        population.individuals = (0..config.population_size)
+            .map(|i| {
+                let id = i as i32 + 1;
+                let mut individual = shifted(&base, id, (i % 5) as f32 * far + (i / 5) as f32 * 0.01);
+                individual.fitness = id as f32;
+                individual
+            })
+            .collect();
+        population.genome_ids.skip_past(config.population_size as i32);
+
+        let new_gen = population.reproduce();
+        assert_eq!(new_gen.len(), config.population_size);
+        assert_eq!(population.species.len(), 5);
+
+        let mut elites: Vec<&Individual> = population.individuals.iter().rev().take(config.elitism).collect();
+        for species in &population.species {
+            elites.extend(
+                species
+                    .members
+                    .iter()
+                    .map(|&i| &population.individuals[i])
+                    .filter(|i| !elites.iter().any(|e| e.genome.id == i.genome.id))
+                    .take(config.elitism_per_species)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        for elite in elites {
+            let copy = new_gen.iter().find(|i| i.genome.id == elite.genome.id).expect("elite was dropped");
+            assert_eq!(format!("{:?}", copy.genome), format!("{:?}", elite.genome));
+        }
+        // Everything else is new
+        let num_kept = new_gen.iter().filter(|i| i.genome.id <= config.population_size as i32).count();
+        assert_eq!(num_kept, config.elitism + 5 * config.elitism_per_species);
+    }
+
+    #[test]
+    fn elites_and_species_minimums_fit_the_population() {
+        let config = crate::config::Config::global();
+        let base = Genome::new(1, 3, 3, &mut derive_rng(9, &[rng::INIT]));
+        let far = config.compatibility_threshold / config.c3_weight + 1.0;
+
+        // Every individual is its own species, too many for each to get `min_species_size`
+        let mut population = Population::new(9);
+        population.individuals = (0..config.population_size)
+            .map(|i| Individual { fitness: i as f32, ..shifted(&base, i as i32 + 1, i as f32 * far) })
+            .collect();
+
+        let new_gen = population.reproduce();
+        assert_eq!(population.species.len(), config.population_size);
+        assert_eq!(new_gen.len(), config.population_size);
+    }
+
+    #[test]
+    fn population_from_genome_has_unique_ids() {
+        let config = crate::config::Config::global();
+        let (num_inputs, num_outputs) = config.genome_shape();
+        let prev = Genome::new(40, num_inputs, num_outputs, &mut derive_rng(9, &[rng::INIT]));
+
+        let mut population = Population::new(9);
+        population.populate_from_genome(prev);
+
+        let mut ids: Vec<i32> = population.individuals.iter().map(|i| i.genome.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), config.population_size);
+        assert!(ids[0] > 40);
+    }
+}
@@ -0,0 +1,107 @@
+use rand::{Rng, RngCore, seq::IndexedRandom};
+
+use super::Individual;
+
+/// Picks parents for reproduction
+pub trait SelectionStrategy: Send + Sync {
+    /// Select one parent from `candidates`, which are sorted best first and never empty
+    fn select<'a>(&self, candidates: &[&'a Individual], rng: &mut dyn RngCore) -> &'a Individual;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectionMethod {
+    Truncation,
+    Tournament,
+    Roulette,
+    Rank,
+}
+
+/// What the population is selected on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Objective {
+    Fitness,
+    Novelty,
+    /// Fitness and novelty, both normalized over the population and mixed with `novelty_weight`
+    Blend,
+    /// NSGA-II over separate objectives instead of one fitness, see `nsga2`
+    Pareto,
+}
+
+/// Only the best `survival_threshold` fraction may reproduce, chosen uniformly
+pub struct TruncationSelection {
+    pub survival_threshold: f32,
+}
+
+impl SelectionStrategy for TruncationSelection {
+    fn select<'a>(&self, candidates: &[&'a Individual], rng: &mut dyn RngCore) -> &'a Individual {
+        let cutoff = ((self.survival_threshold * candidates.len() as f32).ceil() as usize).clamp(1, candidates.len());
+        candidates[..cutoff].choose(rng).unwrap()
+    }
+}
+
+/// Best of `size` individuals drawn at random (with replacement)
+pub struct TournamentSelection {
+    pub size: usize,
+}
+
+impl SelectionStrategy for TournamentSelection {
+    fn select<'a>(&self, candidates: &[&'a Individual], rng: &mut dyn RngCore) -> &'a Individual {
+        // Candidates are sorted best first, so the lowest index wins the tournament
+        let winner = (0..self.size.max(1))
+            .map(|_| rng.random_range(0..candidates.len()))
+            .min()
+            .unwrap();
+        candidates[winner]
+    }
+}
+
+/// Fitness proportional selection. Fitness is shifted so the worst candidate still has a small
+/// chance to be picked.
+pub struct RouletteSelection;
+
+impl SelectionStrategy for RouletteSelection {
+    fn select<'a>(&self, candidates: &[&'a Individual], rng: &mut dyn RngCore) -> &'a Individual {
+        let min_fitness = candidates.iter().map(|i| i.fitness).fold(f32::INFINITY, f32::min);
+        let weights: Vec<f32> = candidates.iter().map(|i| i.fitness - min_fitness + 1.0).collect();
+        pick_weighted(candidates, &weights, rng)
+    }
+}
+
+/// Linear ranking: the best of n candidates has weight n, the worst weight 1
+pub struct RankSelection;
+
+impl SelectionStrategy for RankSelection {
+    fn select<'a>(&self, candidates: &[&'a Individual], rng: &mut dyn RngCore) -> &'a Individual {
+        let n = candidates.len();
+        let weights: Vec<f32> = (0..n).map(|rank| (n - rank) as f32).collect();
+        pick_weighted(candidates, &weights, rng)
+    }
+}
+
+fn pick_weighted<'a>(candidates: &[&'a Individual], weights: &[f32], rng: &mut dyn RngCore) -> &'a Individual {
+    let total: f32 = weights.iter().sum();
+    let mut target = rng.random::<f32>() * total;
+    for (candidate, weight) in candidates.iter().zip(weights) {
+        if target < *weight {
+            return candidate;
+        }
+        target -= weight;
+    }
+    // Floating point rounding can leave a tiny remainder
+    candidates.last().unwrap()
+}
+
+/// Build the strategy selected by `Config::selection`
+pub fn from_config() -> Box<dyn SelectionStrategy> {
+    let config = crate::config::Config::global();
+    match config.selection {
+        SelectionMethod::Truncation => Box::new(TruncationSelection {
+            survival_threshold: config.survival_threshold,
+        }),
+        SelectionMethod::Tournament => Box::new(TournamentSelection {
+            size: config.tournament_size,
+        }),
+        SelectionMethod::Roulette => Box::new(RouletteSelection),
+        SelectionMethod::Rank => Box::new(RankSelection),
+    }
+}
@@ -0,0 +1,116 @@
+use super::Genome;
+use crate::config::Config;
+
+#[derive(Clone, Debug)]
+pub struct Species {
+    pub id: i32,
+    pub representative: Genome,
+    // Indices into the population's individuals, best first
+    pub members: Vec<usize>,
+    // Sum of the members' shared fitness, decides how many offspring the species gets
+    pub adjusted_fitness: f32,
+    // Stagnation tracking
+    pub best_fitness: f32,
+    pub last_improved: usize,
+}
+
+impl Species {
+    pub fn new(id: i32, representative: Genome, generation: usize) -> Self {
+        Self {
+            id,
+            representative,
+            members: Vec::new(),
+            adjusted_fitness: 0.0,
+            best_fitness: f32::NEG_INFINITY,
+            last_improved: generation,
+        }
+    }
+
+    pub fn generations_without_improvement(&self, generation: usize) -> usize {
+        // A resumed run can start counting generations below `last_improved`
+        generation.saturating_sub(self.last_improved)
+    }
+}
+
+/// A species that was removed for not improving
+#[derive(Clone, Debug)]
+pub struct StagnationEvent {
+    pub species_id: i32,
+    pub generations_without_improvement: usize,
+    pub best_fitness: f32,
+}
+
+impl Genome {
+    /// Compatibility distance from the NEAT paper:
+    /// delta = c1 * E / N + c2 * D / N + c3 * W
+    /// where E is the number of excess genes, D the number of disjoint genes, N the size of the
+    /// larger genome, and W the average weight difference of matching genes.
+    pub fn distance(&self, other: &Genome, config: &Config) -> f32 {
+        let (a, b) = (self, other);
+
+        let max_innovation_a = a.links.iter().map(|l| l.innovation).max().unwrap_or(i32::MIN);
+        let max_innovation_b = b.links.iter().map(|l| l.innovation).max().unwrap_or(i32::MIN);
+
+        let mut excess = 0;
+        let mut disjoint = 0;
+        let mut matching = 0;
+        let mut weight_diff = 0.0;
+
+        for link in &a.links {
+            if let Some(other) = b.find_link_by_innovation(link.innovation) {
+                matching += 1;
+                weight_diff += (link.weight - other.weight).abs();
+            } else if link.innovation > max_innovation_b {
+                excess += 1;
+            } else {
+                disjoint += 1;
+            }
+        }
+
+        for link in &b.links {
+            if a.find_link_by_innovation(link.innovation).is_some() {
+                continue;
+            }
+            if link.innovation > max_innovation_a {
+                excess += 1;
+            } else {
+                disjoint += 1;
+            }
+        }
+
+        let n = a.links.len().max(b.links.len()).max(1) as f32;
+        let avg_weight_diff = if matching > 0 {
+            weight_diff / matching as f32
+        } else {
+            0.0
+        };
+
+        config.c1_excess * excess as f32 / n
+            + config.c2_disjoint * disjoint as f32 / n
+            + config.c3_weight * avg_weight_diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neat::test_utils::link;
+
+    fn genome(links: &[(i32, f32)]) -> Genome {
+        let links = links.iter().map(|&(innovation, weight)| link(innovation, innovation + 1, innovation, weight, true));
+        crate::neat::test_utils::genome(0, 0, &[], links.collect())
+    }
+
+    #[test]
+    fn distance_counts_excess_disjoint_and_weights() {
+        let config = Config::global();
+        let a = genome(&[(0, 1.0), (1, 1.0), (3, 1.0)]);
+        let b = genome(&[(0, 0.0), (2, 1.0), (3, 1.0), (4, 1.0), (5, 1.0)]);
+
+        // Matching 0 and 3, disjoint 1 and 2, excess 4 and 5
+        let expected = config.c1_excess * 2.0 / 5.0 + config.c2_disjoint * 2.0 / 5.0 + config.c3_weight * 0.5;
+        assert!((a.distance(&b, config) - expected).abs() < 1e-6);
+        assert_eq!(a.distance(&b, config), b.distance(&a, config));
+        assert_eq!(a.distance(&a, config), 0.0);
+    }
+}
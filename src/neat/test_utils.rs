@@ -0,0 +1,38 @@
+//! Fixtures shared by the unit tests
+
+use super::activation::ActivationFunction;
+use super::aggregation::AggregationFunction;
+use super::{Genome, Individual, LinkGene, LinkID, NeuronGene};
+
+pub fn link(in_id: i32, out_id: i32, innovation: i32, weight: f32, is_enabled: bool) -> LinkGene {
+    LinkGene {
+        id: LinkID { in_id, out_id },
+        weight,
+        is_enabled,
+        innovation,
+    }
+}
+
+/// Genome with the given inputs and outputs, the `hidden` neurons and `links`.
+/// Every neuron has a zero bias and the default activation for its ID.
+pub fn genome(num_inputs: i32, num_outputs: i32, hidden: &[i32], links: Vec<LinkGene>) -> Genome {
+    let ids = (1..=num_inputs).map(|i| -i).chain(0..num_outputs).chain(hidden.iter().copied());
+    Genome {
+        id: 0,
+        num_inputs,
+        num_outputs,
+        neurons: ids
+            .map(|id| NeuronGene {
+                id,
+                bias: 0.0,
+                activation: ActivationFunction::default_for(id, num_outputs),
+                aggregation: AggregationFunction::Sum,
+            })
+            .collect(),
+        links,
+    }
+}
+
+pub fn individual(genome: Genome, fitness: f32) -> Individual {
+    Individual { genome, fitness }
+}
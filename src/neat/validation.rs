@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::activation::ActivationFunction;
+use super::mutation::would_create_cycle;
+use super::{Genome, LinkGene, LinkID, NeuronGene};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GenomeIssue {
+    /// More than one neuron gene with this ID
+    DuplicateNeuron(i32),
+    /// An input or output neuron is missing
+    MissingNeuron(i32),
+    /// The link connects a neuron that doesn't exist
+    DanglingLink(LinkID),
+    /// More than one link gene with this ID
+    DuplicateLink(LinkID),
+    /// The link feeds into an input neuron
+    LinkIntoInput(LinkID),
+    /// More than one link gene with this innovation number, crossover can't line them up
+    DuplicateInnovation(i32),
+    /// The link closes a cycle, which feed forward networks can't evaluate
+    Cycle(LinkID),
+}
+
+impl fmt::Display for GenomeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenomeIssue::DuplicateNeuron(id) => write!(f, "duplicate neuron {}", id),
+            GenomeIssue::MissingNeuron(id) => write!(f, "missing input/output neuron {}", id),
+            GenomeIssue::DanglingLink(id) => write!(f, "link {} -> {} to a missing neuron", id.in_id, id.out_id),
+            GenomeIssue::DuplicateLink(id) => write!(f, "duplicate link {} -> {}", id.in_id, id.out_id),
+            GenomeIssue::LinkIntoInput(id) => write!(f, "link {} -> {} into an input neuron", id.in_id, id.out_id),
+            GenomeIssue::DuplicateInnovation(innovation) => write!(f, "duplicate innovation number {}", innovation),
+            GenomeIssue::Cycle(id) => write!(f, "link {} -> {} closes a cycle", id.in_id, id.out_id),
+        }
+    }
+}
+
+/// True if the links contain a cycle (Kahn's algorithm)
+fn has_cycle(links: &[LinkGene]) -> bool {
+    let mut in_degree: HashMap<i32, usize> = HashMap::new();
+    let mut outgoing: HashMap<i32, Vec<i32>> = HashMap::new();
+    for link in links {
+        in_degree.entry(link.id.in_id).or_insert(0);
+        *in_degree.entry(link.id.out_id).or_insert(0) += 1;
+        outgoing.entry(link.id.in_id).or_default().push(link.id.out_id);
+    }
+
+    let mut stack: Vec<i32> = in_degree.iter().filter(|(_, d)| **d == 0).map(|(id, _)| *id).collect();
+    let mut visited = 0;
+    while let Some(node) = stack.pop() {
+        visited += 1;
+        for out_id in outgoing.get(&node).into_iter().flatten() {
+            let degree = in_degree.get_mut(out_id).unwrap();
+            *degree -= 1;
+            if *degree == 0 {
+                stack.push(*out_id);
+            }
+        }
+    }
+
+    visited < in_degree.len()
+}
+
+impl Genome {
+    /// Check the genome for structural problems, reporting every issue found
+    pub fn validate(&self) -> Result<(), Vec<GenomeIssue>> {
+        let issues = self.clone().repair();
+        if issues.is_empty() { Ok(()) } else { Err(issues) }
+    }
+
+    /// Fix every structural problem in place and return what was fixed. Duplicates keep their
+    /// first occurrence, missing inputs/outputs are added back, dangling links and links into
+    /// inputs are removed and, unless recurrent networks are allowed, every link closing a cycle
+    /// is removed.
+    pub fn repair(&mut self) -> Vec<GenomeIssue> {
+        let config = crate::config::Config::global();
+        let mut issues = Vec::new();
+
+        let mut seen: HashSet<i32> = HashSet::new();
+        self.neurons.retain(|n| {
+            let first = seen.insert(n.id);
+            if !first {
+                issues.push(GenomeIssue::DuplicateNeuron(n.id));
+            }
+            first
+        });
+
+        for id in self.make_input_ids().into_iter().chain(self.make_output_ids()) {
+            if seen.insert(id) {
+                issues.push(GenomeIssue::MissingNeuron(id));
+                self.neurons.push(NeuronGene {
+                    id,
+                    bias: 0.0,
+                    activation: ActivationFunction::default_for(id, self.num_outputs),
+                    aggregation: config.default_aggregation,
+                });
+            }
+        }
+
+        let inputs: HashSet<i32> = self.make_input_ids().into_iter().collect();
+        let mut seen_links: HashSet<LinkID> = HashSet::new();
+        let mut seen_innovations: HashSet<i32> = HashSet::new();
+        self.links.retain(|l| {
+            if !seen.contains(&l.id.in_id) || !seen.contains(&l.id.out_id) {
+                issues.push(GenomeIssue::DanglingLink(l.id.clone()));
+                return false;
+            }
+            if inputs.contains(&l.id.out_id) {
+                issues.push(GenomeIssue::LinkIntoInput(l.id.clone()));
+                return false;
+            }
+            if !seen_links.insert(l.id.clone()) {
+                issues.push(GenomeIssue::DuplicateLink(l.id.clone()));
+                return false;
+            }
+            if !seen_innovations.insert(l.innovation) {
+                issues.push(GenomeIssue::DuplicateInnovation(l.innovation));
+                return false;
+            }
+            true
+        });
+
+        // Cheap check first, only look for the offending links if there is a cycle at all
+        if !config.allow_recurrent && has_cycle(&self.links) {
+            let mut acyclic: Vec<LinkGene> = Vec::new();
+            for link in self.links.drain(..) {
+                if would_create_cycle(&acyclic, link.id.in_id, link.id.out_id) {
+                    issues.push(GenomeIssue::Cycle(link.id.clone()));
+                } else {
+                    acyclic.push(link);
+                }
+            }
+            self.links = acyclic;
+        }
+
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neat::rng::derive_rng;
+    use crate::neat::test_utils::link;
+
+    #[test]
+    fn repair_fixes_every_issue() {
+        let mut genome = Genome::new(0, 2, 1, &mut derive_rng(0, &[]));
+        let link = |in_id, out_id, innovation| link(in_id, out_id, innovation, 1.0, true);
+
+        // Output 0 missing, duplicate input, hidden 5 -> 6 -> 5 cycle, dangling and duplicate
+        // links, a link into an input and a reused innovation number
+        genome.neurons.retain(|n| n.id != 0);
+        genome.neurons.push(genome.neurons[0].clone());
+        for id in [5, 6] {
+            genome.neurons.push(NeuronGene { id, ..genome.neurons[0].clone() });
+        }
+        genome.links.extend([
+            link(5, 6, 100),
+            link(6, 5, 101),
+            link(-1, 42, 102),
+            link(5, 6, 103),
+            link(5, -2, 104),
+            link(-2, 6, 100),
+        ]);
+
+        let issues = genome.validate().unwrap_err();
+        assert!(issues.contains(&GenomeIssue::MissingNeuron(0)));
+        assert!(issues.contains(&GenomeIssue::DuplicateNeuron(-1)));
+        assert!(issues.contains(&GenomeIssue::Cycle(LinkID { in_id: 6, out_id: 5 })));
+        assert!(issues.contains(&GenomeIssue::DanglingLink(LinkID { in_id: -1, out_id: 42 })));
+        assert!(issues.contains(&GenomeIssue::DuplicateLink(LinkID { in_id: 5, out_id: 6 })));
+        assert!(issues.contains(&GenomeIssue::LinkIntoInput(LinkID { in_id: 5, out_id: -2 })));
+        assert!(issues.contains(&GenomeIssue::DuplicateInnovation(100)));
+
+        assert_eq!(genome.repair(), issues);
+        assert_eq!(genome.validate(), Ok(()));
+    }
+}
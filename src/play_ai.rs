@@ -11,13 +11,42 @@ use breakout::{
 };
 use macroquad::{prelude::*, text};
 use neat::Genome;
-use neat::nn::FeedForwardNeuralNetwork;
+use neat::map_elites::Elite;
+use training::{create_game_network, game_state};
 
 #[macroquad::main("Arkanoid - AI Playing")]
 async fn main() {
+    // Elites of a MAP-Elites archive, switched between with N and P
+    let mut elites: Vec<Elite> = Vec::new();
+    let mut current_elite = 0;
+
     // Load the best genome
     let mut genome: Genome;
-    if std::env::args().any(|x| &x == "--champion") {
+    if std::env::args().any(|x| &x == "--archive") {
+        elites = match serialization::load_map_elites("map_elites.pb") {
+            Ok(archive) => archive.elites().into_iter().cloned().collect(),
+            Err(e) => {
+                eprintln!("Failed to load archive: {}", e);
+                eprintln!("Please run training first: cargo run --release --bin breakout-map-elites");
+                return;
+            }
+        };
+        if elites.is_empty() {
+            eprintln!("The archive has no elites yet");
+            return;
+        }
+        let mut args = std::env::args();
+        if args.any(|x| &x == "--elite") {
+            current_elite = args
+                .next()
+                .expect("Parameter for elite flag not given!")
+                .parse::<usize>()
+                .expect("Invalid elite index!")
+                .min(elites.len() - 1);
+        }
+        println!("Successfully loaded {} elites!", elites.len());
+        genome = elites[current_elite].individual.genome.clone();
+    } else if std::env::args().any(|x| &x == "--champion") {
         genome = match serialization::load_genome("best_of_the_best.pb") {
             Ok(g) => {
                 println!("Successfully loaded **CHAMPION** genome!");
@@ -44,7 +73,7 @@ async fn main() {
     }
 
     let mut engine = BreakoutEngine::new();
-    let mut network = FeedForwardNeuralNetwork::create_from_genome(&genome);
+    let mut network = create_game_network(&genome, &engine);
 
     setup_camera(engine.scr_w, engine.scr_h);
 
@@ -68,7 +97,7 @@ async fn main() {
             render_game(&engine);
 
             // Get current game state
-            let state = engine.get_state();
+            let state = game_state(&engine);
 
             // Run neural network to get action
             let outputs = network.activate(state.clone());
@@ -94,8 +123,8 @@ async fn main() {
             draw_text_ex(
                 &format!(
                     "Ball: ({:.1}, {:.1})",
-                    state[0] * engine.scr_w,
-                    state[1] * engine.scr_h
+                    engine.ball_x,
+                    engine.ball_y
                 ),
                 0.5,
                 13.0,
@@ -168,10 +197,41 @@ async fn main() {
             );
         }
 
+        // Browse the archive, every elite plays its own game
+        if !elites.is_empty() {
+            let previous_elite = current_elite;
+            if is_key_pressed(KeyCode::N) {
+                current_elite = (current_elite + 1) % elites.len();
+            } else if is_key_pressed(KeyCode::P) {
+                current_elite = (current_elite + elites.len() - 1) % elites.len();
+            }
+            if current_elite != previous_elite {
+                genome = elites[current_elite].individual.genome.clone();
+                engine.reset();
+                engine.stick = false;
+                network = create_game_network(&genome, &engine);
+            }
+
+            let elite = &elites[current_elite];
+            draw_text_ex(
+                &format!(
+                    "Elite {}/{} (N/P): paddle x {:.2}, edge hits {:.2}, blocks {:.2}",
+                    current_elite + 1,
+                    elites.len(),
+                    elite.descriptors[0],
+                    elite.descriptors[1],
+                    elite.descriptors[2]
+                ),
+                0.5,
+                17.,
+                text_params.clone(),
+            );
+        }
+
         // Reset on game over
         if engine.game_over && is_key_pressed(KeyCode::R) {
             engine.reset();
-            network = FeedForwardNeuralNetwork::create_from_genome(&genome);
+            network.reset();
             engine.stick = false;
         }
 
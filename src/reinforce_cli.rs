@@ -0,0 +1,157 @@
+mod breakout;
+mod config;
+mod neat;
+mod rl;
+mod serialization;
+mod training;
+
+use neat::{GenomeIdAllocator, Individual};
+use neat::rng::{self, derive_rng};
+use rayon::prelude::*;
+use rl::reinforce::Reinforce;
+
+// Games the greedy policy plays after every generation, scored like NEAT individuals
+const EVAL_EPISODES: usize = 10;
+
+fn main() {
+    println!("Starting REINFORCE training on Breakout...");
+    println!(
+        "Using parallel processing with {} CPU cores",
+        rayon::current_num_threads()
+    );
+
+    let config = config::Config::global();
+    let mut num_generations = config.num_generations;
+    let mut num_steps = config.num_steps;
+    let mut seed = config.seed;
+
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--num-gens" {
+            num_generations = args
+                .next()
+                .expect("Parameter for num-gen flag not given!")
+                .parse()
+                .expect("Invalid num-gen number!");
+            println!("num-gens flag loaded successfully, num_gens: {num_generations}");
+        }
+        if arg == "--num-steps" {
+            num_steps = args
+                .next()
+                .expect("Parameter for num-steps flag not given!")
+                .parse()
+                .expect("Invalid num-steps number!");
+            println!("num-steps flag loaded successfully, num_steps: {num_steps}");
+        }
+        if arg == "--seed" {
+            seed = Some(
+                args.next()
+                    .expect("Parameter for seed flag not given!")
+                    .parse()
+                    .expect("Invalid seed number!"),
+            );
+        }
+    }
+
+    if config.hyperneat {
+        eprintln!("REINFORCE learns on the game state directly, turn off hyperneat in the config");
+        return;
+    }
+    assert_eq!(config.num_outputs as usize, rl::ACTIONS.len(), "one network output per action");
+
+    let seed = seed.unwrap_or_else(rand::random);
+    let mut sizes = vec![config.num_inputs as usize];
+    sizes.extend(&config.reinforce_hidden_layers);
+    sizes.push(config.num_outputs as usize);
+    let mut agent = Reinforce::new(&sizes, &mut derive_rng(seed, &[rng::INIT]));
+
+    // Print the seed so any run can be reproduced with --seed
+    println!("Seed: {}", seed);
+    println!("Network: {:?}", sizes);
+
+    // Every snapshot of the policy that gets scored or saved is a genome of its own
+    let genome_ids = GenomeIdAllocator::new(1);
+    let mut best = Individual { genome: agent.policy.to_genome(0), fitness: f32::NEG_INFINITY };
+    let mut best_generation = 0;
+    let overall_start = std::time::Instant::now();
+
+    for generation in 0..num_generations {
+        println!(
+            "\n=== Generation {}/{} ===",
+            generation + 1,
+            num_generations
+        );
+
+        let start_time = std::time::Instant::now();
+        let trajectories = agent.collect(config.reinforce_episodes_per_generation, num_steps, seed, generation);
+        let duration = start_time.elapsed();
+
+        let fitness: Vec<f32> = trajectories.iter().map(|t| t.fitness).collect();
+        let avg_fitness = fitness.iter().sum::<f32>() / fitness.len() as f32;
+        println!("  Evaluation time: {:.2}s", duration.as_secs_f32());
+        println!(
+            "  Evaluations/sec: {:.2}",
+            trajectories.len() as f32 / duration.as_secs_f32()
+        );
+        println!("  Avg fitness: {:.2}", avg_fitness);
+        println!("  Min fitness: {:.2}", fitness.iter().copied().fold(f32::INFINITY, f32::min));
+        println!("  Max fitness: {:.2}", fitness.iter().copied().fold(f32::NEG_INFINITY, f32::max));
+        println!(
+            "  Avg reward: {:.2}",
+            trajectories.iter().map(|t| t.rewards.iter().sum::<f32>()).sum::<f32>() / trajectories.len() as f32
+        );
+
+        // Training samples its actions but the viewer plays the most likely one, so the best
+        // policy is picked by how the greedy policy scores on games it didn't train on
+        let policy = Individual { genome: agent.policy.to_genome(genome_ids.next_id()), fitness: 0.0 };
+        let greedy_fitness = (0..EVAL_EPISODES)
+            .into_par_iter()
+            .map(|i| {
+                let mut rng = derive_rng(seed, &[rng::VALIDATE, generation as u64, i as u64]);
+                training::evaluate_individual(&policy, num_steps, &mut rng)
+            })
+            .sum::<f32>()
+            / EVAL_EPISODES as f32;
+        println!("  Greedy fitness: {:.2}", greedy_fitness);
+
+        if greedy_fitness > best.fitness {
+            best = Individual { fitness: greedy_fitness, ..policy };
+            best_generation = generation + 1;
+        }
+        println!("  Best fitness: {:.2}", best.fitness);
+
+        let entropy = agent.update(&trajectories);
+        println!("  Policy entropy: {:.3}", entropy);
+    }
+
+    let total_duration = overall_start.elapsed();
+
+    println!("\n=== Training Complete! ===");
+    println!("Total training time: {:.2}s", total_duration.as_secs_f32());
+    println!(
+        "Average time per generation: {:.2}s",
+        total_duration.as_secs_f32() / num_generations as f32
+    );
+    println!("Best policy: generation {} (genome {})", best_generation, best.genome.id);
+    println!("Best fitness: {:.2}", best.fitness);
+
+    // Save the best individual
+    if let Err(e) = serialization::save_individual(&best, "best_individual.pb") {
+        eprintln!("Failed to save best individual: {}", e);
+    }
+
+    // Also save just the genome
+    if let Err(e) = serialization::save_genome(&best.genome, "best_genome.pb") {
+        eprintln!("Failed to save best genome: {}", e);
+    }
+
+    // The final policy, whatever it scores
+    if let Err(e) = serialization::save_genome(&agent.policy.to_genome(genome_ids.next_id()), "reinforce_genome.pb") {
+        eprintln!("Failed to save REINFORCE genome: {}", e);
+    }
+
+    println!("\nResults saved to:");
+    println!("  - best_individual.pb");
+    println!("  - best_genome.pb");
+    println!("  - reinforce_genome.pb");
+}
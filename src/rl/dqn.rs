@@ -0,0 +1,178 @@
+//! Deep Q-learning (Mnih et al. 2015): an MLP estimates the return of every action, trained on
+//! minibatches from a replay buffer against a periodically synced target network.
+
+use rand::Rng;
+use rand::seq::IndexedRandom;
+
+use super::mlp::{Adam, Mlp};
+
+#[derive(Clone, Debug)]
+pub struct Transition {
+    pub state: Vec<f32>,
+    pub action: usize,
+    pub reward: f32,
+    pub next_state: Vec<f32>,
+    // The episode ended with this transition, nothing is bootstrapped from `next_state`
+    pub done: bool,
+}
+
+/// Fixed size buffer of past transitions, the oldest ones are overwritten first
+pub struct ReplayBuffer {
+    transitions: Vec<Transition>,
+    capacity: usize,
+    next: usize,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            transitions: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }
+    }
+
+    pub fn push(&mut self, transition: Transition) {
+        if self.transitions.len() < self.capacity {
+            self.transitions.push(transition);
+        } else {
+            self.transitions[self.next] = transition;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    /// Uniform sample with replacement
+    pub fn sample(&self, batch_size: usize, rng: &mut impl Rng) -> Vec<&Transition> {
+        (0..batch_size).map(|_| self.transitions.choose(rng).unwrap()).collect()
+    }
+}
+
+pub struct Dqn {
+    pub online: Mlp,
+    target: Mlp,
+    optimizer: Adam,
+    pub replay: ReplayBuffer,
+    // Transitions observed so far
+    pub steps: usize,
+}
+
+impl Dqn {
+    /// `sizes` lists the width of every layer, inputs first and one output per action
+    pub fn new(sizes: &[usize], rng: &mut impl Rng) -> Self {
+        let config = crate::config::Config::global();
+        let online = Mlp::new(sizes, rng);
+        Self {
+            target: online.clone(),
+            optimizer: Adam::new(&online, config.dqn_learning_rate),
+            online,
+            replay: ReplayBuffer::new(config.dqn_replay_capacity),
+            steps: 0,
+        }
+    }
+
+    /// Exploration rate, decays linearly from `dqn_epsilon_start` to `dqn_epsilon_end`
+    pub fn epsilon(&self) -> f32 {
+        let config = crate::config::Config::global();
+        let progress = (self.steps as f32 / config.dqn_epsilon_decay_steps as f32).min(1.0);
+        config.dqn_epsilon_start + (config.dqn_epsilon_end - config.dqn_epsilon_start) * progress
+    }
+
+    pub fn greedy(&self, state: &[f32]) -> usize {
+        argmax(&self.online.forward(state))
+    }
+
+    /// Epsilon-greedy action
+    pub fn act(&self, state: &[f32], rng: &mut impl Rng) -> usize {
+        if rng.random::<f32>() < self.epsilon() {
+            rng.random_range(0..self.online.layers.last().unwrap().num_outputs)
+        } else {
+            self.greedy(state)
+        }
+    }
+
+    /// Store a transition and learn from the replay buffer every `dqn_train_every` steps once
+    /// it holds `dqn_warmup_steps` transitions. Returns the loss if a batch was trained.
+    pub fn observe(&mut self, transition: Transition, rng: &mut impl Rng) -> Option<f32> {
+        let config = crate::config::Config::global();
+        self.replay.push(transition);
+        self.steps += 1;
+
+        if self.steps.is_multiple_of(config.dqn_target_sync) {
+            self.target = self.online.clone();
+        }
+        if self.replay.len() < config.dqn_warmup_steps || !self.steps.is_multiple_of(config.dqn_train_every) {
+            return None;
+        }
+        Some(self.train_batch(rng))
+    }
+
+    /// One gradient step on a minibatch with the Huber loss between Q(s, a) and
+    /// r + gamma * max Q_target(s', a'). Returns the mean loss.
+    pub fn train_batch(&mut self, rng: &mut impl Rng) -> f32 {
+        let config = crate::config::Config::global();
+        let batch = self.replay.sample(config.dqn_batch_size, rng);
+
+        let mut gradient = self.online.zeros_like();
+        let mut loss = 0.0;
+        for transition in &batch {
+            let mut target = transition.reward;
+            if !transition.done {
+                let next_q = self.target.forward(&transition.next_state);
+                target += config.dqn_gamma * next_q.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            }
+
+            let activations = self.online.forward_all(&transition.state);
+            let q = activations.last().unwrap();
+            let error = q[transition.action] - target;
+            loss += if error.abs() <= 1.0 { 0.5 * error * error } else { error.abs() - 0.5 };
+
+            // Only the taken action's output gets a gradient, clipped like the Huber loss
+            let mut output_gradient = vec![0.0; q.len()];
+            output_gradient[transition.action] = error.clamp(-1.0, 1.0) / batch.len() as f32;
+            self.online.backward(&activations, &output_gradient, &mut gradient);
+        }
+
+        self.optimizer.step(&mut self.online, &mut gradient);
+        loss / batch.len() as f32
+    }
+}
+
+pub fn argmax(values: &[f32]) -> usize {
+    (0..values.len())
+        .max_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn learns_the_value_of_every_action() {
+        let mut rng = crate::neat::rng::derive_rng(2, &[]);
+        let mut dqn = Dqn::new(&[2, 16, 3], &mut rng);
+        let state = vec![0.5, -0.5];
+
+        // One step episodes, only the last action pays
+        for _ in 0..3000 {
+            for action in 0..3 {
+                dqn.replay.push(Transition {
+                    state: state.clone(),
+                    action,
+                    reward: if action == 2 { 1.0 } else { 0.0 },
+                    next_state: state.clone(),
+                    done: true,
+                });
+            }
+            dqn.train_batch(&mut rng);
+        }
+
+        let q = dqn.online.forward(&state);
+        assert_eq!(dqn.greedy(&state), 2);
+        assert!((q[2] - 1.0).abs() < 0.1 && q[0].abs() < 0.1 && q[1].abs() < 0.1, "{:?}", q);
+    }
+}
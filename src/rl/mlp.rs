@@ -0,0 +1,235 @@
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+use crate::neat::activation::ActivationFunction;
+use crate::neat::aggregation::AggregationFunction;
+use crate::neat::innovation::InnovationTracker;
+use crate::neat::{Genome, LinkGene, LinkID, NeuronGene};
+
+/// Fully connected layer, `weights` holds one row of `num_inputs` weights per output
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub num_inputs: usize,
+    pub num_outputs: usize,
+    pub weights: Vec<f32>,
+    pub biases: Vec<f32>,
+}
+
+/// Multi-layer perceptron with ReLU hidden layers and linear outputs
+#[derive(Clone, Debug)]
+pub struct Mlp {
+    pub layers: Vec<Layer>,
+}
+
+impl Mlp {
+    /// `sizes` lists the width of every layer, inputs first. Weights use He initialization.
+    pub fn new(sizes: &[usize], rng: &mut impl Rng) -> Self {
+        let layers = sizes
+            .windows(2)
+            .map(|w| {
+                let normal = Normal::new(0.0, (2.0 / w[0] as f32).sqrt()).unwrap();
+                Layer {
+                    num_inputs: w[0],
+                    num_outputs: w[1],
+                    weights: (0..w[0] * w[1]).map(|_| normal.sample(rng)).collect(),
+                    biases: vec![0.0; w[1]],
+                }
+            })
+            .collect();
+        Self { layers }
+    }
+
+    /// Same shape, every parameter 0. Used to accumulate gradients.
+    pub fn zeros_like(&self) -> Self {
+        let mut zeros = self.clone();
+        zeros.parameters_mut().for_each(|p| *p = 0.0);
+        zeros
+    }
+
+    pub fn parameters_mut(&mut self) -> impl Iterator<Item = &mut f32> {
+        self.layers
+            .iter_mut()
+            .flat_map(|layer| layer.weights.iter_mut().chain(layer.biases.iter_mut()))
+    }
+
+    pub fn num_parameters(&self) -> usize {
+        self.layers.iter().map(|l| l.weights.len() + l.biases.len()).sum()
+    }
+
+    /// Output of every layer, starting with the input itself
+    pub fn forward_all(&self, input: &[f32]) -> Vec<Vec<f32>> {
+        let mut activations = vec![input.to_vec()];
+        for (index, layer) in self.layers.iter().enumerate() {
+            let x = activations.last().unwrap();
+            let is_output = index == self.layers.len() - 1;
+            let y = (0..layer.num_outputs)
+                .map(|o| {
+                    let row = &layer.weights[o * layer.num_inputs..(o + 1) * layer.num_inputs];
+                    let value = row.iter().zip(x).map(|(w, x)| w * x).sum::<f32>() + layer.biases[o];
+                    if is_output { value } else { value.max(0.0) }
+                })
+                .collect();
+            activations.push(y);
+        }
+        activations
+    }
+
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        self.forward_all(input).pop().unwrap()
+    }
+
+    /// Backpropagate the gradient of the loss with respect to the outputs through the layers,
+    /// adding the parameter gradients to `gradient`. `activations` come from `forward_all`.
+    pub fn backward(&self, activations: &[Vec<f32>], output_gradient: &[f32], gradient: &mut Mlp) {
+        let mut delta = output_gradient.to_vec();
+        for index in (0..self.layers.len()).rev() {
+            let layer = &self.layers[index];
+            let x = &activations[index];
+            let grad_layer = &mut gradient.layers[index];
+
+            let mut input_delta = vec![0.0; layer.num_inputs];
+            for (o, d) in delta.iter().enumerate() {
+                grad_layer.biases[o] += d;
+                for (i, xi) in x.iter().enumerate() {
+                    grad_layer.weights[o * layer.num_inputs + i] += d * xi;
+                    input_delta[i] += d * layer.weights[o * layer.num_inputs + i];
+                }
+            }
+
+            // Through the ReLU of the previous layer, the input layer has none
+            if index > 0 {
+                for (d, xi) in input_delta.iter_mut().zip(x) {
+                    if *xi <= 0.0 {
+                        *d = 0.0;
+                    }
+                }
+            }
+            delta = input_delta;
+        }
+    }
+
+    /// The same network as a genome, so the AI viewer can play it. Hidden neurons get IDs after
+    /// the outputs, layer by layer.
+    pub fn to_genome(&self, id: i32) -> Genome {
+        let num_inputs = self.layers[0].num_inputs as i32;
+        let num_outputs = self.layers.last().unwrap().num_outputs as i32;
+        let innovations = InnovationTracker::new(num_inputs, num_outputs);
+        let mut genome = Genome {
+            id,
+            num_inputs,
+            num_outputs,
+            neurons: Vec::new(),
+            links: Vec::new(),
+        };
+
+        let mut layer_ids: Vec<Vec<i32>> = vec![genome.make_input_ids()];
+        let mut next_hidden_id = num_outputs;
+        for (index, layer) in self.layers.iter().enumerate() {
+            let ids: Vec<i32> = if index == self.layers.len() - 1 {
+                genome.make_output_ids()
+            } else {
+                next_hidden_id += layer.num_outputs as i32;
+                (next_hidden_id - layer.num_outputs as i32..next_hidden_id).collect()
+            };
+            layer_ids.push(ids);
+        }
+
+        for &id in &layer_ids[0] {
+            genome.neurons.push(NeuronGene {
+                id,
+                bias: 0.0,
+                activation: ActivationFunction::default_for(id, num_outputs),
+                aggregation: AggregationFunction::Sum,
+            });
+        }
+        for (index, layer) in self.layers.iter().enumerate() {
+            let is_output = index == self.layers.len() - 1;
+            for (o, &out_id) in layer_ids[index + 1].iter().enumerate() {
+                genome.neurons.push(NeuronGene {
+                    id: out_id,
+                    bias: layer.biases[o],
+                    activation: if is_output { ActivationFunction::Identity } else { ActivationFunction::Relu },
+                    aggregation: AggregationFunction::Sum,
+                });
+                for (i, &in_id) in layer_ids[index].iter().enumerate() {
+                    let link_id = LinkID { in_id, out_id };
+                    genome.links.push(LinkGene {
+                        innovation: innovations.link_innovation(&link_id),
+                        id: link_id,
+                        weight: layer.weights[o * layer.num_inputs + i],
+                        is_enabled: true,
+                    });
+                }
+            }
+        }
+        genome
+    }
+}
+
+/// Adam optimizer (Kingma & Ba 2015), one moment estimate per parameter
+pub struct Adam {
+    pub learning_rate: f32,
+    m: Vec<f32>,
+    v: Vec<f32>,
+    t: i32,
+}
+
+impl Adam {
+    pub fn new(network: &Mlp, learning_rate: f32) -> Self {
+        Self {
+            learning_rate,
+            m: vec![0.0; network.num_parameters()],
+            v: vec![0.0; network.num_parameters()],
+            t: 0,
+        }
+    }
+
+    /// Move the parameters against the gradient (gradient descent)
+    pub fn step(&mut self, network: &mut Mlp, gradient: &mut Mlp) {
+        const BETA1: f32 = 0.9;
+        const BETA2: f32 = 0.999;
+        const EPSILON: f32 = 1e-8;
+
+        self.t += 1;
+        let (bias1, bias2) = (1.0 - BETA1.powi(self.t), 1.0 - BETA2.powi(self.t));
+        for (i, (p, g)) in network.parameters_mut().zip(gradient.parameters_mut()).enumerate() {
+            self.m[i] = BETA1 * self.m[i] + (1.0 - BETA1) * *g;
+            self.v[i] = BETA2 * self.v[i] + (1.0 - BETA2) * *g * *g;
+            *p -= self.learning_rate * (self.m[i] / bias1) / ((self.v[i] / bias2).sqrt() + EPSILON);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neat::nn::create_network;
+
+    #[test]
+    fn backprop_matches_finite_differences_and_genome_matches_forward() {
+        let mut rng = crate::neat::rng::derive_rng(1, &[]);
+        let mut mlp = Mlp::new(&[3, 4, 4, 2], &mut rng);
+        for bias in mlp.layers.iter_mut().flat_map(|l| l.biases.iter_mut()) {
+            *bias = 0.1;
+        }
+        let input = [0.3, -0.7, 0.5];
+
+        // Loss is the first output
+        let mut gradient = mlp.zeros_like();
+        mlp.backward(&mlp.forward_all(&input), &[1.0, 0.0], &mut gradient);
+        let analytic: Vec<f32> = gradient.parameters_mut().map(|g| *g).collect();
+        for (i, expected) in analytic.iter().enumerate() {
+            let mut shifted = mlp.clone();
+            *shifted.parameters_mut().nth(i).unwrap() += 1e-3;
+            let numeric = (shifted.forward(&input)[0] - mlp.forward(&input)[0]) / 1e-3;
+            assert!((numeric - expected).abs() < 1e-2, "parameter {}: {} vs {}", i, numeric, expected);
+        }
+
+        let genome = mlp.to_genome(0);
+        assert_eq!(genome.validate(), Ok(()));
+        let outputs = create_network(&genome).activate(input.to_vec());
+        for (a, b) in outputs.iter().zip(mlp.forward(&input)) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Gradient based reinforcement learning baselines. The agents play `BreakoutEngine` through
+//! `step`/`get_state` and learn from per-step rewards instead of the episode fitness.
+
+pub mod dqn;
+pub mod mlp;
+pub mod reinforce;
+
+use crate::breakout::{BreakoutEngine, engine::Action};
+
+/// Actions in the order of the network outputs, the same order NEAT networks use
+pub const ACTIONS: [Action; 3] = [Action::Left, Action::Stay, Action::Right];
+
+// Rewards for destroying a block, bouncing the ball off the paddle and losing the ball
+pub const BLOCK_REWARD: f32 = 1.0;
+pub const PADDLE_REWARD: f32 = 0.1;
+pub const LOSS_REWARD: f32 = -1.0;
+
+/// Play one frame with the action at index `action` of `ACTIONS`, returning the reward
+pub fn step(engine: &mut BreakoutEngine, action: usize) -> f32 {
+    let blocks = engine.destroyed_blocks.len();
+    let paddle_hits = engine.paddle_hits;
+
+    // Same frame time as `training::run_episode`
+    engine.step(ACTIONS[action], 1.0 / 60.0);
+
+    let mut reward = (engine.destroyed_blocks.len() - blocks) as f32 * BLOCK_REWARD
+        + (engine.paddle_hits - paddle_hits) as f32 * PADDLE_REWARD;
+    if engine.game_over && engine.blocks.iter().flatten().any(|&b| b) {
+        reward += LOSS_REWARD;
+    }
+    reward
+}
@@ -0,0 +1,188 @@
+//! REINFORCE with a baseline (Williams 1992): a softmax policy over `ACTIONS` follows the
+//! gradient of the log probability of every action taken, weighted by how much better the
+//! return was than a learned estimate of the state's value.
+
+use rand::Rng;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::Distribution;
+use rayon::prelude::*;
+
+use super::mlp::{Adam, Mlp};
+use crate::neat::rng::{self, derive_rng};
+use crate::training;
+
+/// One game played by the policy
+pub struct Trajectory {
+    pub states: Vec<Vec<f32>>,
+    pub actions: Vec<usize>,
+    pub rewards: Vec<f32>,
+    // Game fitness, the same score NEAT individuals get
+    pub fitness: f32,
+}
+
+pub fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = logits.iter().map(|l| (l - max).exp()).collect();
+    let sum: f32 = exp.iter().sum();
+    exp.iter().map(|e| e / sum).collect()
+}
+
+/// Return from every step on: the step's reward plus the discounted rewards after it
+pub fn discounted_returns(rewards: &[f32], gamma: f32) -> Vec<f32> {
+    let mut returns = vec![0.0; rewards.len()];
+    let mut future = 0.0;
+    for (i, reward) in rewards.iter().enumerate().rev() {
+        future = reward + gamma * future;
+        returns[i] = future;
+    }
+    returns
+}
+
+pub struct Reinforce {
+    // Outputs the logits of the actions
+    pub policy: Mlp,
+    // Estimates the return from a state
+    pub baseline: Mlp,
+    policy_optimizer: Adam,
+    baseline_optimizer: Adam,
+}
+
+impl Reinforce {
+    /// `sizes` lists the width of every policy layer, inputs first and one output per action.
+    /// The baseline has the same hidden layers and a single output.
+    pub fn new(sizes: &[usize], rng: &mut impl Rng) -> Self {
+        let config = crate::config::Config::global();
+        let policy = Mlp::new(sizes, rng);
+        let mut baseline_sizes = sizes.to_vec();
+        *baseline_sizes.last_mut().unwrap() = 1;
+        let baseline = Mlp::new(&baseline_sizes, rng);
+        Self {
+            policy_optimizer: Adam::new(&policy, config.reinforce_learning_rate),
+            baseline_optimizer: Adam::new(&baseline, config.reinforce_baseline_learning_rate),
+            policy,
+            baseline,
+        }
+    }
+
+    pub fn probabilities(&self, state: &[f32]) -> Vec<f32> {
+        softmax(&self.policy.forward(state))
+    }
+
+    pub fn sample(&self, state: &[f32], rng: &mut impl Rng) -> usize {
+        WeightedIndex::new(self.probabilities(state)).unwrap().sample(rng)
+    }
+
+    /// Play one game of at most `num_steps` frames, sampling every action from the policy
+    pub fn play(&self, num_steps: usize, rng: &mut impl Rng) -> Trajectory {
+        let mut engine = training::new_episode(rng);
+        let mut trajectory = Trajectory { states: Vec::new(), actions: Vec::new(), rewards: Vec::new(), fitness: 0.0 };
+        for _ in 0..num_steps {
+            if engine.game_over {
+                break;
+            }
+            let state = engine.get_state();
+            let action = self.sample(&state, rng);
+            trajectory.rewards.push(super::step(&mut engine, action));
+            trajectory.states.push(state);
+            trajectory.actions.push(action);
+        }
+        trajectory.fitness = engine.calculate_fitness();
+        trajectory
+    }
+
+    /// Play `num_episodes` games in parallel, each with its own RNG stream
+    pub fn collect(&self, num_episodes: usize, num_steps: usize, seed: u64, generation: usize) -> Vec<Trajectory> {
+        (0..num_episodes)
+            .into_par_iter()
+            .map(|index| {
+                let mut rng = derive_rng(seed, &[rng::EVALUATE, generation as u64, index as u64]);
+                self.play(num_steps, &mut rng)
+            })
+            .collect()
+    }
+
+    /// One gradient step for the policy and the baseline on every step of the trajectories.
+    /// Advantages are normalized over the batch. Returns the mean entropy of the policy.
+    pub fn update(&mut self, trajectories: &[Trajectory]) -> f32 {
+        let config = crate::config::Config::global();
+
+        let mut states: Vec<&Vec<f32>> = Vec::new();
+        let mut actions: Vec<usize> = Vec::new();
+        let mut returns: Vec<f32> = Vec::new();
+        for trajectory in trajectories {
+            states.extend(&trajectory.states);
+            actions.extend(&trajectory.actions);
+            returns.extend(discounted_returns(&trajectory.rewards, config.reinforce_gamma));
+        }
+        if states.is_empty() {
+            return 0.0;
+        }
+        let n = states.len() as f32;
+
+        let values: Vec<f32> = states.iter().map(|s| self.baseline.forward(s)[0]).collect();
+        let mut advantages: Vec<f32> = returns.iter().zip(&values).map(|(g, v)| g - v).collect();
+        let mean = advantages.iter().sum::<f32>() / n;
+        let std = (advantages.iter().map(|a| (a - mean) * (a - mean)).sum::<f32>() / n).sqrt();
+        for advantage in advantages.iter_mut() {
+            *advantage = (*advantage - mean) / (std + 1e-8);
+        }
+
+        let mut policy_gradient = self.policy.zeros_like();
+        let mut baseline_gradient = self.baseline.zeros_like();
+        let mut entropy = 0.0;
+        for (i, state) in states.iter().enumerate() {
+            // Gradient of -log pi(a|s) * advantage with respect to the logits
+            let activations = self.policy.forward_all(state);
+            let probabilities = softmax(activations.last().unwrap());
+            entropy -= probabilities.iter().map(|p| p * p.max(1e-8).ln()).sum::<f32>();
+            let output_gradient: Vec<f32> = probabilities
+                .iter()
+                .enumerate()
+                .map(|(a, p)| (p - if a == actions[i] { 1.0 } else { 0.0 }) * advantages[i] / n)
+                .collect();
+            self.policy.backward(&activations, &output_gradient, &mut policy_gradient);
+
+            // Squared error of the baseline
+            let activations = self.baseline.forward_all(state);
+            let error = activations.last().unwrap()[0] - returns[i];
+            self.baseline.backward(&activations, &[error / n], &mut baseline_gradient);
+        }
+
+        self.policy_optimizer.step(&mut self.policy, &mut policy_gradient);
+        self.baseline_optimizer.step(&mut self.baseline, &mut baseline_gradient);
+        entropy / n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_moves_towards_the_rewarded_action() {
+        let returns = discounted_returns(&[1.0, 0.0, 2.0], 0.5);
+        assert_eq!(returns, vec![1.5, 1.0, 2.0]);
+
+        let mut rng = crate::neat::rng::derive_rng(3, &[]);
+        let mut reinforce = Reinforce::new(&[2, 8, 3], &mut rng);
+        let state = vec![0.5, -0.5];
+
+        // One step games, only the last action pays
+        for _ in 0..300 {
+            let trajectories: Vec<Trajectory> = (0..16)
+                .map(|_| {
+                    let action = reinforce.sample(&state, &mut rng);
+                    Trajectory {
+                        states: vec![state.clone()],
+                        actions: vec![action],
+                        rewards: vec![if action == 2 { 1.0 } else { 0.0 }],
+                        fitness: 0.0,
+                    }
+                })
+                .collect();
+            reinforce.update(&trajectories);
+        }
+
+        assert!(reinforce.probabilities(&state)[2] > 0.9, "{:?}", reinforce.probabilities(&state));
+    }
+}
@@ -1,4 +1,12 @@
-use crate::neat::{Genome, Individual, LinkGene, LinkID, NeuronGene};
+use crate::neat::{Genome, GenomeIdAllocator, Individual, LinkGene, LinkID, NeuronGene};
+use crate::neat::activation::ActivationFunction;
+use crate::neat::aggregation::AggregationFunction;
+use crate::neat::hyperneat::{CPPN_INPUTS, CPPN_OUTPUTS};
+use crate::neat::innovation::InnovationTracker;
+use crate::neat::map_elites::{Elite, MapElites};
+use crate::neat::novelty::NoveltyArchive;
+use crate::neat::population::Population;
+use crate::neat::species::Species;
 use std::fs;
 use std::io::{Read, Write};
 use prost::Message;
@@ -9,21 +17,86 @@ pub mod proto {
 }
 
 // Conversion functions from NEAT types to Proto types
+impl From<ActivationFunction> for proto::Activation {
+    fn from(activation: ActivationFunction) -> Self {
+        match activation {
+            ActivationFunction::Sigmoid => proto::Activation::Sigmoid,
+            ActivationFunction::Tanh => proto::Activation::Tanh,
+            ActivationFunction::Relu => proto::Activation::Relu,
+            ActivationFunction::Identity => proto::Activation::Identity,
+            ActivationFunction::Gaussian => proto::Activation::Gaussian,
+            ActivationFunction::Sin => proto::Activation::Sin,
+            ActivationFunction::Abs => proto::Activation::Abs,
+            ActivationFunction::Step => proto::Activation::Step,
+        }
+    }
+}
+
+/// Unspecified activations come from genomes saved before activations were genes, those used
+/// ReLU for hidden neurons and identity for outputs.
+fn activation_from_proto(activation: proto::Activation, id: i32, num_outputs: i32) -> ActivationFunction {
+    match activation {
+        proto::Activation::Unspecified => {
+            if id >= 0 && id < num_outputs {
+                ActivationFunction::Identity
+            } else {
+                ActivationFunction::Relu
+            }
+        }
+        proto::Activation::Sigmoid => ActivationFunction::Sigmoid,
+        proto::Activation::Tanh => ActivationFunction::Tanh,
+        proto::Activation::Relu => ActivationFunction::Relu,
+        proto::Activation::Identity => ActivationFunction::Identity,
+        proto::Activation::Gaussian => ActivationFunction::Gaussian,
+        proto::Activation::Sin => ActivationFunction::Sin,
+        proto::Activation::Abs => ActivationFunction::Abs,
+        proto::Activation::Step => ActivationFunction::Step,
+    }
+}
+
+impl From<AggregationFunction> for proto::Aggregation {
+    fn from(aggregation: AggregationFunction) -> Self {
+        match aggregation {
+            AggregationFunction::Sum => proto::Aggregation::Sum,
+            AggregationFunction::Product => proto::Aggregation::Product,
+            AggregationFunction::Max => proto::Aggregation::Max,
+            AggregationFunction::Min => proto::Aggregation::Min,
+            AggregationFunction::Mean => proto::Aggregation::Mean,
+        }
+    }
+}
+
+// Sum is the zero value, so genomes saved before aggregations were genes load as before
+impl From<proto::Aggregation> for AggregationFunction {
+    fn from(aggregation: proto::Aggregation) -> Self {
+        match aggregation {
+            proto::Aggregation::Sum => AggregationFunction::Sum,
+            proto::Aggregation::Product => AggregationFunction::Product,
+            proto::Aggregation::Max => AggregationFunction::Max,
+            proto::Aggregation::Min => AggregationFunction::Min,
+            proto::Aggregation::Mean => AggregationFunction::Mean,
+        }
+    }
+}
+
 impl From<&NeuronGene> for proto::NeuronGene {
     fn from(gene: &NeuronGene) -> Self {
         proto::NeuronGene {
             id: gene.id,
             bias: gene.bias,
+            activation: proto::Activation::from(gene.activation).into(),
+            aggregation: proto::Aggregation::from(gene.aggregation).into(),
         }
     }
 }
 
-impl From<&proto::NeuronGene> for NeuronGene {
-    fn from(proto: &proto::NeuronGene) -> Self {
-        NeuronGene {
-            id: proto.id,
-            bias: proto.bias,
-        }
+// Neurons need to know the genome's number of outputs, see `From<&proto::Genome>`
+fn neuron_from_proto(proto: &proto::NeuronGene, num_outputs: i32) -> NeuronGene {
+    NeuronGene {
+        id: proto.id,
+        bias: proto.bias,
+        activation: activation_from_proto(proto.activation(), proto.id, num_outputs),
+        aggregation: proto.aggregation().into(),
     }
 }
 
@@ -51,6 +124,7 @@ impl From<&LinkGene> for proto::LinkGene {
             id: Some((&gene.id).into()),
             weight: gene.weight,
             is_enabled: gene.is_enabled,
+            innovation: gene.innovation,
         }
     }
 }
@@ -61,6 +135,7 @@ impl From<&proto::LinkGene> for LinkGene {
             id: proto.id.as_ref().unwrap().into(),
             weight: proto.weight,
             is_enabled: proto.is_enabled,
+            innovation: proto.innovation,
         }
     }
 }
@@ -73,19 +148,26 @@ impl From<&Genome> for proto::Genome {
             num_outputs: genome.num_outputs,
             neurons: genome.neurons.iter().map(|n| n.into()).collect(),
             links: genome.links.iter().map(|l| l.into()).collect(),
+            has_innovations: true,
+            cppn: crate::config::Config::global().hyperneat,
         }
     }
 }
 
 impl From<&proto::Genome> for Genome {
     fn from(proto: &proto::Genome) -> Self {
-        Genome {
+        let mut genome = Genome {
             id: proto.id,
             num_inputs: proto.num_inputs,
             num_outputs: proto.num_outputs,
-            neurons: proto.neurons.iter().map(|n| n.into()).collect(),
+            neurons: proto.neurons.iter().map(|n| neuron_from_proto(n, proto.num_outputs)).collect(),
             links: proto.links.iter().map(|l| l.into()).collect(),
+        };
+        // Input -> output links get their usual numbers, the rest are numbered in order
+        if !proto.has_innovations {
+            InnovationTracker::new(proto.num_inputs, proto.num_outputs).register_genome(&mut genome);
         }
+        genome
     }
 }
 
@@ -107,6 +189,150 @@ impl From<&proto::Individual> for Individual {
     }
 }
 
+impl From<&Species> for proto::Species {
+    fn from(species: &Species) -> Self {
+        proto::Species {
+            id: species.id,
+            representative: Some((&species.representative).into()),
+            best_fitness: species.best_fitness,
+            last_improved: species.last_improved as u64,
+        }
+    }
+}
+
+impl From<&proto::Species> for Species {
+    fn from(proto: &proto::Species) -> Self {
+        let mut species = Species::new(proto.id, proto.representative.as_ref().unwrap().into(), proto.last_improved as usize);
+        species.best_fitness = proto.best_fitness;
+        species
+    }
+}
+
+impl From<&Population> for proto::Population {
+    fn from(population: &Population) -> Self {
+        proto::Population {
+            individuals: population.individuals.iter().map(|i| i.into()).collect(),
+            best: Some((&population.best).into()),
+            generation: population.generation as u64,
+            seed: population.seed,
+            next_genome_id: population.genome_ids.peek(),
+            next_species_id: population.next_species_id,
+            next_innovation: population.innovations.next_innovation(),
+            next_neuron_id: population.innovations.next_neuron_id(),
+            link_innovations: population
+                .innovations
+                .link_innovations()
+                .iter()
+                .map(|(id, innovation)| proto::LinkInnovation {
+                    id: Some(id.into()),
+                    innovation: *innovation,
+                })
+                .collect(),
+            species: population.species.iter().map(|s| s.into()).collect(),
+        }
+    }
+}
+
+impl From<&proto::Population> for Population {
+    fn from(proto: &proto::Population) -> Self {
+        let mut population = Population::new(proto.seed);
+        population.individuals = proto.individuals.iter().map(|i| i.into()).collect();
+        population.best = proto.best.as_ref().unwrap().into();
+        population.generation = proto.generation as usize;
+        population.genome_ids = GenomeIdAllocator::new(proto.next_genome_id);
+        population.next_species_id = proto.next_species_id;
+        population.innovations = InnovationTracker::restore(
+            proto.next_innovation,
+            proto.next_neuron_id,
+            proto
+                .link_innovations
+                .iter()
+                .map(|l| (l.id.as_ref().unwrap().into(), l.innovation))
+                .collect(),
+        );
+        population.species = proto.species.iter().map(|s| s.into()).collect();
+        population
+    }
+}
+
+impl From<&MapElites> for proto::MapElitesArchive {
+    fn from(archive: &MapElites) -> Self {
+        proto::MapElitesArchive {
+            resolution: archive.resolution.iter().map(|&n| n as u32).collect(),
+            elites: archive
+                .elites()
+                .iter()
+                .map(|elite| proto::Elite {
+                    individual: Some((&elite.individual).into()),
+                    descriptors: elite.descriptors.clone(),
+                })
+                .collect(),
+            generation: archive.generation as u64,
+            seed: archive.seed,
+            next_genome_id: archive.genome_ids.peek(),
+            next_innovation: archive.innovations.next_innovation(),
+            next_neuron_id: archive.innovations.next_neuron_id(),
+            link_innovations: archive
+                .innovations
+                .link_innovations()
+                .iter()
+                .map(|(id, innovation)| proto::LinkInnovation {
+                    id: Some(id.into()),
+                    innovation: *innovation,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&proto::MapElitesArchive> for MapElites {
+    fn from(proto: &proto::MapElitesArchive) -> Self {
+        let mut archive = MapElites::new(proto.resolution.iter().map(|&n| n as usize).collect(), proto.seed);
+        for elite in &proto.elites {
+            let elite = Elite {
+                individual: elite.individual.as_ref().unwrap().into(),
+                descriptors: elite.descriptors.clone(),
+            };
+            archive.cells.insert(archive.cell(&elite.descriptors), elite);
+        }
+        archive.generation = proto.generation as usize;
+        archive.genome_ids = GenomeIdAllocator::new(proto.next_genome_id);
+        archive.innovations = InnovationTracker::restore(
+            proto.next_innovation,
+            proto.next_neuron_id,
+            proto
+                .link_innovations
+                .iter()
+                .map(|l| (l.id.as_ref().unwrap().into(), l.innovation))
+                .collect(),
+        );
+        archive
+    }
+}
+
+/// HyperNEAT runs evolve CPPNs and the others evolve the networks themselves, so refuse
+/// genomes saved in the other mode instead of building nonsense networks out of them
+fn check_mode<'a>(genomes: impl IntoIterator<Item = &'a proto::Genome>) -> std::io::Result<()> {
+    let hyperneat = crate::config::Config::global().hyperneat;
+    for genome in genomes {
+        // Older files don't say, but a CPPN has the CPPN's inputs and outputs
+        let cppn = if genome.has_innovations {
+            genome.cppn
+        } else {
+            (genome.num_inputs, genome.num_outputs) == (CPPN_INPUTS, CPPN_OUTPUTS)
+        };
+        if cppn != hyperneat {
+            let saved = if cppn { "a HyperNEAT CPPN" } else { "a network" };
+            let expected = if hyperneat { "a HyperNEAT CPPN" } else { "a network" };
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("genome {} was saved as {}, the config expects {}, check hyperneat", genome.id, saved, expected),
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// Save a genome to a file using protobuf
 pub fn save_genome(genome: &Genome, filename: &str) -> std::io::Result<()> {
     let proto_genome: proto::Genome = genome.into();
@@ -132,8 +358,21 @@ pub fn load_genome(filename: &str) -> std::io::Result<Genome> {
         std::io::Error::new(std::io::ErrorKind::InvalidData, e)
     })?;
     
+    check_mode([&proto_genome])?;
     println!("Genome loaded from {}", filename);
-    Ok((&proto_genome).into())
+    if !proto_genome.has_innovations {
+        println!("  Assigned innovation numbers, the file was saved without them");
+    }
+    let mut genome: Genome = (&proto_genome).into();
+    repair_loaded(&mut genome);
+    Ok(genome)
+}
+
+/// Repair a genome read from disk, a corrupted file must not crash the networks built from it
+fn repair_loaded(genome: &mut Genome) {
+    for issue in genome.repair() {
+        println!("  Repaired genome {}: {}", genome.id, issue);
+    }
 }
 
 /// Save an individual to a file using protobuf
@@ -161,6 +400,225 @@ pub fn load_individual(filename: &str) -> std::io::Result<Individual> {
         std::io::Error::new(std::io::ErrorKind::InvalidData, e)
     })?;
     
+    check_mode(&proto_individual.genome)?;
     println!("Individual loaded from {}", filename);
-    Ok((&proto_individual).into())
+    let mut individual: Individual = (&proto_individual).into();
+    repair_loaded(&mut individual.genome);
+    Ok(individual)
+}
+
+/// Save a population checkpoint, including everything needed to resume the run
+pub fn save_population(population: &Population, filename: &str) -> std::io::Result<()> {
+    let proto_population: proto::Population = population.into();
+    let mut buf = Vec::new();
+    proto_population.encode(&mut buf).map_err(std::io::Error::other)?;
+
+    let mut file = fs::File::create(filename)?;
+    file.write_all(&buf)?;
+
+    println!("Checkpoint saved to {}", filename);
+    Ok(())
+}
+
+/// Load a population checkpoint saved with `save_population`
+pub fn load_population(filename: &str) -> std::io::Result<Population> {
+    let mut file = fs::File::open(filename)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    let proto_population = proto::Population::decode(&buf[..]).map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    })?;
+
+    check_mode(proto_population.individuals.iter().filter_map(|i| i.genome.as_ref()))?;
+    println!("Checkpoint loaded from {}", filename);
+    let mut population: Population = (&proto_population).into();
+    for individual in population.individuals.iter_mut() {
+        repair_loaded(&mut individual.genome);
+    }
+    repair_loaded(&mut population.best.genome);
+    for species in population.species.iter_mut() {
+        repair_loaded(&mut species.representative);
+    }
+    Ok(population)
+}
+
+/// Save the members of a Pareto front with their objective values
+pub fn save_pareto_front(members: &[(Individual, Vec<f32>)], generation: usize, filename: &str) -> std::io::Result<()> {
+    let proto_front = proto::ParetoFront {
+        generation: generation as u64,
+        members: members
+            .iter()
+            .map(|(individual, objectives)| proto::ParetoMember {
+                individual: Some(individual.into()),
+                objectives: objectives.clone(),
+            })
+            .collect(),
+    };
+    let mut buf = Vec::new();
+    proto_front.encode(&mut buf).map_err(std::io::Error::other)?;
+
+    let mut file = fs::File::create(filename)?;
+    file.write_all(&buf)?;
+
+    println!("Pareto front saved to {}", filename);
+    Ok(())
+}
+
+/// Load a Pareto front saved with `save_pareto_front`
+pub fn load_pareto_front(filename: &str) -> std::io::Result<Vec<(Individual, Vec<f32>)>> {
+    let mut file = fs::File::open(filename)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    let proto_front = proto::ParetoFront::decode(&buf[..]).map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    })?;
+
+    check_mode(proto_front.members.iter().filter_map(|m| m.individual.as_ref()?.genome.as_ref()))?;
+    println!("Pareto front of generation {} loaded from {}", proto_front.generation, filename);
+    let mut members: Vec<(Individual, Vec<f32>)> = proto_front
+        .members
+        .iter()
+        .map(|m| (m.individual.as_ref().unwrap().into(), m.objectives.clone()))
+        .collect();
+    for (individual, _) in members.iter_mut() {
+        repair_loaded(&mut individual.genome);
+    }
+    Ok(members)
+}
+
+/// Save a MAP-Elites archive with every elite and the state needed to continue filling it
+pub fn save_map_elites(archive: &MapElites, filename: &str) -> std::io::Result<()> {
+    let proto_archive: proto::MapElitesArchive = archive.into();
+    let mut buf = Vec::new();
+    proto_archive.encode(&mut buf).map_err(std::io::Error::other)?;
+
+    let mut file = fs::File::create(filename)?;
+    file.write_all(&buf)?;
+
+    println!("MAP-Elites archive saved to {}", filename);
+    Ok(())
+}
+
+/// Load a MAP-Elites archive saved with `save_map_elites`
+pub fn load_map_elites(filename: &str) -> std::io::Result<MapElites> {
+    let mut file = fs::File::open(filename)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    let proto_archive = proto::MapElitesArchive::decode(&buf[..]).map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    })?;
+
+    if proto_archive.resolution.contains(&0) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("archive resolution {:?} has a dimension without cells", proto_archive.resolution),
+        ));
+    }
+    check_mode(proto_archive.elites.iter().filter_map(|e| e.individual.as_ref()?.genome.as_ref()))?;
+    println!("MAP-Elites archive loaded from {}", filename);
+    let mut archive: MapElites = (&proto_archive).into();
+    for elite in archive.cells.values_mut() {
+        repair_loaded(&mut elite.individual.genome);
+    }
+    Ok(archive)
+}
+
+/// Save the novelty search archive as JSON
+pub fn save_archive(archive: &NoveltyArchive, filename: &str) -> std::io::Result<()> {
+    let json = serde_json::to_string(archive).map_err(std::io::Error::other)?;
+    fs::write(filename, json)?;
+
+    println!("Novelty archive saved to {}", filename);
+    Ok(())
+}
+
+/// Load a novelty search archive saved with `save_archive`
+pub fn load_archive(filename: &str) -> std::io::Result<NoveltyArchive> {
+    let json = fs::read_to_string(filename)?;
+    let archive = serde_json::from_str(&json).map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    })?;
+
+    println!("Novelty archive loaded from {}", filename);
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumed_population_continues_the_same_run() {
+        let mut population = Population::new(7);
+        population.populate();
+        for _ in 0..3 {
+            for (i, individual) in population.individuals.iter_mut().enumerate() {
+                individual.fitness = (i % 13) as f32;
+            }
+            population.individuals = population.reproduce();
+        }
+
+        let checkpoint: proto::Population = (&population).into();
+        let mut resumed: Population = (&checkpoint).into();
+        assert_eq!(proto::Population::from(&resumed), checkpoint);
+
+        // Both continue with the same genomes and never reuse an ID
+        for population in [&mut population, &mut resumed] {
+            for (i, individual) in population.individuals.iter_mut().enumerate() {
+                individual.fitness = (i % 7) as f32;
+            }
+            population.individuals = population.reproduce();
+        }
+        let genomes = |p: &Population| p.individuals.iter().map(|i| (&i.genome).into()).collect::<Vec<proto::Genome>>();
+        assert_eq!(genomes(&population), genomes(&resumed));
+        assert!(resumed.individuals.iter().all(|i| i.genome.id < resumed.genome_ids.peek()));
+    }
+
+    #[test]
+    fn genomes_without_innovations_get_them_on_load() {
+        let rng = &mut crate::neat::rng::derive_rng(4, &[]);
+        let innovations = InnovationTracker::new(3, 3);
+        let mut genome = Genome::new(0, 3, 3, rng);
+        for _ in 0..5 {
+            crate::neat::mutation::mutate_add_neuron(&mut genome, &innovations, rng);
+            innovations.new_generation();
+        }
+        let innovation_numbers = |g: &Genome| g.links.iter().map(|l| l.innovation).collect::<Vec<i32>>();
+
+        let saved: proto::Genome = (&genome).into();
+        assert_eq!(innovation_numbers(&(&saved).into()), innovation_numbers(&genome));
+
+        // As written before innovation numbers were saved
+        let mut old = saved.clone();
+        old.has_innovations = false;
+        for link in old.links.iter_mut() {
+            link.innovation = 0;
+        }
+        let loaded: Genome = (&old).into();
+        assert_eq!(innovation_numbers(&loaded), innovation_numbers(&genome));
+        assert_eq!(proto::Genome::from(&loaded), saved);
+    }
+
+    #[test]
+    fn genomes_of_the_other_mode_are_refused() {
+        let config = crate::config::Config::global();
+        let (num_inputs, num_outputs) = config.genome_shape();
+        let genome = Genome::new(1, num_inputs, num_outputs, &mut crate::neat::rng::derive_rng(5, &[]));
+        let mut saved: proto::Genome = (&genome).into();
+        assert_eq!(saved.cppn, config.hyperneat);
+        assert!(check_mode([&saved]).is_ok());
+
+        saved.cppn = !config.hyperneat;
+        assert!(check_mode([&saved]).is_err());
+
+        // Without the flag the shape decides
+        saved.has_innovations = false;
+        assert!(check_mode([&saved]).is_ok());
+        let cppn: proto::Genome = (&Genome::new(2, CPPN_INPUTS, CPPN_OUTPUTS, &mut crate::neat::rng::derive_rng(5, &[]))).into();
+        let old_cppn = proto::Genome { has_innovations: false, ..cppn };
+        assert_eq!(check_mode([&old_cppn]).is_ok(), config.hyperneat);
+    }
 }
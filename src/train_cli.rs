@@ -4,7 +4,11 @@ mod neat;
 mod serialization;
 mod training;
 
+use neat::Individual;
+use neat::novelty::{self, NoveltyArchive};
+use neat::nsga2;
 use neat::population::Population;
+use neat::selection::Objective;
 
 fn main() {
     println!("Starting NEAT training on Breakout...");
@@ -13,28 +17,12 @@ fn main() {
         rayon::current_num_threads()
     );
 
-    let mut population = Population::new();
-
-    if std::env::args().any(|x| &x == "--prev") {
-        let genome = match serialization::load_genome("best_genome.pb") {
-            Ok(g) => {
-                println!("Successfully loaded best genome!");
-                g
-            }
-            Err(e) => {
-                eprintln!("Failed to load genome: {}", e);
-                eprintln!("Please run training first: cargo run --release --bin breakout-train");
-                return;
-            }
-        };
-        population.populate_from_genome(genome);
-    } else {
-        population.populate();
-    }
-
     let config = config::Config::global();
     let mut num_generations = config.num_generations;
     let mut num_steps = config.num_steps;
+    let mut seed = config.seed;
+    let mut objective = config.objective;
+    let mut export_front: Option<usize> = None;
 
     let mut args = std::env::args();
     while let Some(arg) = args.next() {
@@ -54,7 +42,107 @@ fn main() {
                 .expect("Invalid num-steps number!");
             println!("num-steps flag loaded successfully, num_steps: {num_steps}");
         }
+        if arg == "--objective" {
+            objective = match args.next().expect("Parameter for objective flag not given!").as_str() {
+                "fitness" => Objective::Fitness,
+                "novelty" => Objective::Novelty,
+                "blend" => Objective::Blend,
+                "pareto" => Objective::Pareto,
+                _ => panic!("Invalid objective! Use fitness, novelty, blend or pareto"),
+            };
+            println!("objective flag loaded successfully, objective: {objective:?}");
+        }
+        if arg == "--export-front" {
+            export_front = Some(
+                args.next()
+                    .expect("Parameter for export-front flag not given!")
+                    .parse()
+                    .expect("Invalid Pareto front index!"),
+            );
+        }
+        if arg == "--seed" {
+            seed = Some(
+                args.next()
+                    .expect("Parameter for seed flag not given!")
+                    .parse()
+                    .expect("Invalid seed number!"),
+            );
+        }
+    }
+
+    // Write one member of the last Pareto front to best_genome.pb instead of training
+    if let Some(index) = export_front {
+        match serialization::load_pareto_front("pareto_front.pb") {
+            Ok(front) => match front.get(index) {
+                Some((individual, objectives)) => {
+                    println!("Exporting Pareto front member {} (objectives {:?})", index, objectives);
+                    if let Err(e) = serialization::save_genome(&individual.genome, "best_genome.pb") {
+                        eprintln!("Failed to save genome: {}", e);
+                    }
+                }
+                None => eprintln!("The Pareto front only has {} members", front.len()),
+            },
+            Err(e) => eprintln!("Failed to load Pareto front: {}", e),
+        }
+        return;
+    }
+
+    let resume = std::env::args().any(|x| &x == "--resume");
+    let uses_novelty = matches!(objective, Objective::Novelty | Objective::Blend);
+    let mut population = if resume {
+        match serialization::load_population("checkpoint.pb") {
+            Ok(p) => {
+                println!("Resuming from generation {}", p.generation + 1);
+                p
+            }
+            Err(e) => {
+                eprintln!("Failed to load checkpoint: {}", e);
+                return;
+            }
+        }
+    } else {
+        let mut population = Population::new(seed.unwrap_or_else(rand::random));
+
+        if std::env::args().any(|x| &x == "--prev") {
+            let genome = match serialization::load_genome("best_genome.pb") {
+                Ok(g) => {
+                    println!("Successfully loaded best genome!");
+                    g
+                }
+                Err(e) => {
+                    eprintln!("Failed to load genome: {}", e);
+                    eprintln!("Please run training first: cargo run --release --bin breakout-train");
+                    return;
+                }
+            };
+            population.populate_from_genome(genome);
+        } else {
+            population.populate();
+        }
+        population
+    };
+
+    // Print the seed so any run can be reproduced with --seed
+    println!("Seed: {}", population.seed);
+
+    // Behaviors seen in earlier runs stay in the archive when resuming
+    let mut archive = NoveltyArchive::default();
+    if resume && uses_novelty {
+        match serialization::load_archive("novelty_archive.json") {
+            Ok(a) => archive = a,
+            Err(e) => eprintln!("Starting with an empty novelty archive: {}", e),
+        }
+    }
+
+    // NSGA-II parents with their objectives, they compete with every new generation for survival
+    let mut parents: Vec<(Individual, Vec<f32>)> = Vec::new();
+    if resume && objective == Objective::Pareto {
+        match serialization::load_pareto_front("nsga2_parents.pb") {
+            Ok(p) => parents = p,
+            Err(e) => eprintln!("Starting without NSGA-II parents: {}", e),
+        }
     }
+
     let overall_start = std::time::Instant::now();
 
     for generation in 0..num_generations {
@@ -65,7 +153,12 @@ fn main() {
         );
 
         // Train the population with statistics
-        let stats = training::train_population_with_stats(&mut population.individuals, num_steps);
+        let stats = training::train_population_with_stats(
+            &mut population.individuals,
+            num_steps,
+            population.seed,
+            population.generation,
+        );
 
         println!("  Evaluation time: {:.2}s", stats.duration.as_secs_f32());
         println!(
@@ -76,19 +169,94 @@ fn main() {
         println!("  Min fitness: {:.2}", stats.min_fitness);
         println!("  Max fitness: {:.2}", stats.max_fitness);
 
-        // Sort by fitness
-        neat::population::sort_individuals_by_fitness(&mut population.individuals);
-
-        // Get best individual
-        if let Some(best) = population.individuals.first() {
+        // Get best individual, always by game fitness whatever the objective
+        if let Some(best) = population
+            .individuals
+            .iter()
+            .min_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap())
+        {
             population.best = best.clone();
             println!("  Best genome ID: {}", best.genome.id);
             println!("  Best fitness: {:.2}", best.fitness);
         }
 
-        // Reproduce for next generation
-        if generation < num_generations - 1 {
+        // Select on novelty instead of, or blended with, fitness
+        if uses_novelty {
+            let behaviors: Vec<Vec<f32>> = stats.episodes.iter().map(|e| e.behavior.clone()).collect();
+            let novelty = novelty::apply_objective(objective, &mut population.individuals, &behaviors, &mut archive);
+            println!("  Avg novelty: {:.3}", novelty.iter().sum::<f32>() / novelty.len() as f32);
+            println!("  Max novelty: {:.3}", novelty.iter().copied().fold(0.0f32, f32::max));
+            println!("  Archive size: {}", archive.behaviors.len());
+        }
+
+        // NSGA-II replaces speciation: parents and offspring are sorted into fronts together and
+        // the survivors breed the next generation. The first front is saved for --export-front.
+        if objective == Objective::Pareto {
+            let objectives: Vec<Vec<f32>> = stats
+                .episodes
+                .iter()
+                .zip(&population.individuals)
+                .map(|(episode, individual)| episode.objectives(&individual.genome, num_steps))
+                .collect();
+            let mut candidates = std::mem::take(&mut parents);
+            candidates.extend(population.individuals.iter().cloned().zip(objectives));
+            let candidate_objectives: Vec<Vec<f32>> = candidates.iter().map(|(_, o)| o.clone()).collect();
+            let survivors = nsga2::select_survivors(&candidate_objectives, config.population_size);
+            let ranks: Vec<nsga2::Rank> = survivors.iter().map(|&(_, rank)| rank).collect();
+            parents = survivors.iter().map(|&(i, _)| candidates[i].clone()).collect();
+
+            let front: Vec<(Individual, Vec<f32>)> = parents
+                .iter()
+                .zip(&ranks)
+                .filter(|(_, rank)| rank.front == 0)
+                .map(|(member, _)| member.clone())
+                .collect();
+            println!("  Pareto front: {} individuals", front.len());
+            for (member, (individual, objectives)) in front.iter().enumerate().take(10) {
+                let time_to_clear = -objectives[1];
+                println!(
+                    "    [{}] Genome {}: blocks {}, time to clear {}, frames {}, size {}",
+                    member,
+                    individual.genome.id,
+                    objectives[0],
+                    if time_to_clear < num_steps as f32 { time_to_clear.to_string() } else { "-".to_string() },
+                    objectives[2],
+                    -objectives[3],
+                );
+            }
+            if front.len() > 10 {
+                println!("    ... and {} more", front.len() - 10);
+            }
+            if let Err(e) = serialization::save_pareto_front(&front, population.generation, "pareto_front.pb") {
+                eprintln!("Failed to save Pareto front: {}", e);
+            }
+            if let Err(e) = serialization::save_pareto_front(&parents, population.generation, "nsga2_parents.pb") {
+                eprintln!("Failed to save NSGA-II parents: {}", e);
+            }
+
+            let parent_individuals: Vec<Individual> = parents.iter().map(|(individual, _)| individual.clone()).collect();
+            population.individuals = population.reproduce_nsga2(&parent_individuals, &ranks);
+        } else {
+            // Sort by fitness
+            neat::population::sort_individuals_by_fitness(&mut population.individuals);
+
+            // Reproduce for next generation, the checkpoint always holds the next population
             population.individuals = population.reproduce();
+            println!("  Species: {}", population.species.len());
+            for event in &population.stagnated {
+                println!(
+                    "  Species {} removed: stagnated for {} generations (best fitness {:.2})",
+                    event.species_id, event.generations_without_improvement, event.best_fitness
+                );
+            }
+        }
+        if let Err(e) = serialization::save_population(&population, "checkpoint.pb") {
+            eprintln!("Failed to save checkpoint: {}", e);
+        }
+        if uses_novelty
+            && let Err(e) = serialization::save_archive(&archive, "novelty_archive.json")
+        {
+            eprintln!("Failed to save novelty archive: {}", e);
         }
     }
 
@@ -116,4 +284,9 @@ fn main() {
     println!("\nResults saved to:");
     println!("  - best_individual.pb");
     println!("  - best_genome.pb");
+    println!("  - checkpoint.pb (continue with --resume)");
+    if objective == Objective::Pareto {
+        println!("  - pareto_front.pb (export a member with --export-front)");
+        println!("  - nsga2_parents.pb");
+    }
 }
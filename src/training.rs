@@ -1,18 +1,73 @@
 use crate::breakout::{BreakoutEngine, engine::Action};
 use crate::config;
-use crate::neat::{Individual, nn::FeedForwardNeuralNetwork};
+use crate::neat::hyperneat::{self, Substrate};
+use crate::neat::nn::{CompiledNetwork, NeuralNetwork, create_network};
+use crate::neat::{Genome, Individual, rng::{self, derive_rng}};
+use rand::Rng;
 use rayon::prelude::*;
 
-/// Evaluate a single individual on the breakout game
-/// This function is thread-safe and can be called in parallel
-pub fn evaluate_individual(individual: &Individual, num_steps: usize) -> f32 {
+/// Substrate for HyperNEAT, matching `BreakoutEngine::get_full_state`. Positions follow the
+/// screen: the block grid at the top, ball and paddle inputs at the bottom and the outputs
+/// (left, stay, right) from left to right.
+pub fn breakout_substrate(engine: &BreakoutEngine) -> Substrate {
+    let config = config::Config::global();
+    let mut inputs = hyperneat::grid(engine.blocks_w, engine.blocks_h, (-1.0, 1.0), (-1.0, 0.0));
+    inputs.extend(hyperneat::grid(3, 1, (-1.0, 1.0), (1.0, 1.0)));
+
+    Substrate {
+        inputs,
+        hidden: hyperneat::grid(config.substrate_hidden_cols, config.substrate_hidden_rows, (-1.0, 1.0), (-1.0, 1.0)),
+        outputs: hyperneat::grid(config.num_outputs as usize, 1, (-1.0, 1.0), (0.0, 0.0)),
+    }
+}
+
+/// Network playing the game. In HyperNEAT mode the genome is a CPPN that builds the network
+/// on the breakout substrate.
+pub fn create_game_network(genome: &Genome, engine: &BreakoutEngine) -> Box<dyn NeuralNetwork> {
+    if config::Config::global().hyperneat {
+        Box::new(CompiledNetwork::compile(&breakout_substrate(engine).create_network(genome)))
+    } else {
+        create_network(genome)
+    }
+}
+
+/// Network inputs for the current game state, see `create_game_network`
+pub fn game_state(engine: &BreakoutEngine) -> Vec<f32> {
+    if config::Config::global().hyperneat {
+        engine.get_full_state()
+    } else {
+        engine.get_state()
+    }
+}
+
+// Bins of the paddle and ball position histograms in the behavior characterization
+const HISTOGRAM_BINS: usize = 10;
+
+/// Outcome of playing one game
+pub struct Episode {
+    pub fitness: f32,
+    pub blocks_destroyed: usize,
+    pub frames_survived: u32,
+    // Frames it took to destroy every block, if the game was won
+    pub time_to_clear: Option<u32>,
+    /// Behavior characterization for novelty search: for every block how early it was destroyed
+    /// (1 for the first block, 0 if it survived), then histograms of the paddle and ball x
+    /// positions over the episode
+    pub behavior: Vec<f32>,
+    /// Playing style for MAP-Elites, every value in [0, 1]: average paddle x, share of paddle
+    /// hits on the outer fifths of the paddle and share of blocks destroyed
+    pub descriptors: Vec<f32>,
+}
+
+fn histogram_bin(x: f32, width: f32) -> usize {
+    ((x / width * HISTOGRAM_BINS as f32) as usize).min(HISTOGRAM_BINS - 1)
+}
+
+/// A fresh game with a random ball direction and paddle position, already started
+pub fn new_episode(rng: &mut impl Rng) -> BreakoutEngine {
     let mut engine = BreakoutEngine::new();
-    let mut network = FeedForwardNeuralNetwork::create_from_genome(&individual.genome);
-    
-    // Add randomness to make evaluation non-deterministic
+
     // Randomize initial ball direction
-    use rand::Rng;
-    let mut rng = rand::rng();
     let angle: f32 = rng.random_range(-0.5..0.5); // Vary angle by ±0.5 radians
     let speed = 6.5;
     engine.dx = speed * angle.cos();
@@ -23,10 +78,29 @@ pub fn evaluate_individual(individual: &Individual, num_steps: usize) -> f32 {
     
     // Auto-start the game
     engine.stick = false;
+    engine
+}
+
+/// Evaluate a single individual on the breakout game
+/// This function is thread-safe and can be called in parallel
+pub fn evaluate_individual(individual: &Individual, num_steps: usize, rng: &mut impl Rng) -> f32 {
+    run_episode(individual, num_steps, rng).fitness
+}
+
+/// Play one game, recording the fitness and the behavior of the individual
+pub fn run_episode(individual: &Individual, num_steps: usize, rng: &mut impl Rng) -> Episode {
+    let mut engine = new_episode(rng);
+    let mut network = create_game_network(&individual.genome, &engine);
+    // Recurrent networks must not remember anything from a previous episode
+    network.reset();
     
     // Run the game for a maximum number of frames or until game over
     let max_frames = num_steps;
     let delta = 1.0 / 60.0; // Assume 60 FPS
+    let mut outputs = vec![0.0; network.num_outputs()];
+    let mut paddle_histogram = [0.0f32; HISTOGRAM_BINS];
+    let mut ball_histogram = [0.0f32; HISTOGRAM_BINS];
+    let mut paddle_x_sum = 0.0;
     
     for _ in 0..max_frames {
         if engine.game_over {
@@ -34,10 +108,10 @@ pub fn evaluate_individual(individual: &Individual, num_steps: usize) -> f32 {
         }
         
         // Get current game state
-        let state = engine.get_state();
+        let state = game_state(&engine);
         
         // Run neural network to get action
-        let outputs = network.activate(state);
+        network.activate_into(&state, &mut outputs);
         
         // Determine action from network outputs
         // outputs[0] = left, outputs[1] = stay, outputs[2] = right
@@ -51,29 +125,84 @@ pub fn evaluate_individual(individual: &Individual, num_steps: usize) -> f32 {
         
         // Step the game
         engine.step(action, delta);
+        paddle_histogram[histogram_bin(engine.platform_x, engine.scr_w)] += 1.0;
+        ball_histogram[histogram_bin(engine.ball_x, engine.scr_w)] += 1.0;
+        // 0 with the paddle against the left wall, 1 against the right wall
+        let travel = engine.scr_w - engine.platform_width;
+        paddle_x_sum += ((engine.platform_x - engine.platform_width / 2.0) / travel).clamp(0.0, 1.0);
     }
     
-    // Calculate and return fitness
-    engine.calculate_fitness()
+    let num_blocks = engine.blocks_w * engine.blocks_h;
+    let mut behavior = vec![0.0; num_blocks];
+    for (order, (row, col)) in engine.destroyed_blocks.iter().enumerate() {
+        behavior[row * engine.blocks_w + col] = 1.0 - order as f32 / num_blocks as f32;
+    }
+    let frames = engine.frames_alive.max(1) as f32;
+    behavior.extend(paddle_histogram.iter().chain(&ball_histogram).map(|count| count / frames));
+
+    let descriptors = vec![
+        paddle_x_sum / frames,
+        engine.edge_hits as f32 / engine.paddle_hits.max(1) as f32,
+        engine.destroyed_blocks.len() as f32 / num_blocks as f32,
+    ];
+
+    let cleared = engine.blocks.iter().flatten().all(|&b| !b);
+    Episode {
+        fitness: engine.calculate_fitness(),
+        blocks_destroyed: engine.destroyed_blocks.len(),
+        frames_survived: engine.frames_alive,
+        time_to_clear: cleared.then_some(engine.frames_alive),
+        behavior,
+        descriptors,
+    }
+}
+
+impl Episode {
+    /// Objectives for multi-objective selection, all maximized: blocks destroyed, time to clear
+    /// (negated, games that weren't won count as taking `num_steps`), frames survived and network
+    /// size (negated, enabled links plus hidden neurons)
+    pub fn objectives(&self, genome: &Genome, num_steps: usize) -> Vec<f32> {
+        let time_to_clear = self.time_to_clear.map_or(num_steps as f32, |t| t as f32);
+        let hidden_neurons = genome.neurons.iter().filter(|n| n.id >= genome.num_outputs).count();
+        let enabled_links = genome.links.iter().filter(|l| l.is_enabled).count();
+        vec![
+            self.blocks_destroyed as f32,
+            -time_to_clear,
+            self.frames_survived as f32,
+            -((hidden_neurons + enabled_links) as f32),
+        ]
+    }
 }
 
 /// Train the population on the breakout game using parallel processing
 /// This evaluates all individuals in parallel across all available CPU cores
-pub fn train_population(individuals: &mut Vec<Individual>, num_steps: usize) {
-    // Use rayon's parallel iterator to evaluate all individuals concurrently
-    individuals.par_iter_mut().for_each(|individual| {
-        individual.fitness = evaluate_individual(individual, num_steps);
-    });
+/// Returns every individual's episode, in the same order as the individuals
+pub fn train_population(individuals: &mut Vec<Individual>, num_steps: usize, seed: u64, generation: usize) -> Vec<Episode> {
+    // Use rayon's parallel iterator to evaluate all individuals concurrently.
+    // Each episode gets its own RNG so results don't depend on thread scheduling.
+    individuals
+        .par_iter_mut()
+        .enumerate()
+        .map(|(index, individual)| {
+            let mut rng = derive_rng(seed, &[rng::EVALUATE, generation as u64, index as u64]);
+            let episode = run_episode(individual, num_steps, &mut rng);
+            individual.fitness = episode.fitness;
+            episode
+        })
+        .collect()
 }
 
 /// Train the population and return statistics about the run
-pub fn train_population_with_stats(individuals: &mut Vec<Individual>, num_steps: usize) -> TrainingStats {
+pub fn train_population_with_stats(
+    individuals: &mut Vec<Individual>,
+    num_steps: usize,
+    seed: u64,
+    generation: usize,
+) -> TrainingStats {
     let start_time = std::time::Instant::now();
     
     // Parallel evaluation
-    individuals.par_iter_mut().for_each(|individual| {
-        individual.fitness = evaluate_individual(individual, num_steps);
-    });
+    let episodes = train_population(individuals, num_steps, seed, generation);
     
     let duration = start_time.elapsed();
     
@@ -89,6 +218,7 @@ pub fn train_population_with_stats(individuals: &mut Vec<Individual>, num_steps:
         max_fitness,
         min_fitness,
         population_size: individuals.len(),
+        episodes,
     }
 }
 
@@ -98,4 +228,5 @@ pub struct TrainingStats {
     pub max_fitness: f32,
     pub min_fitness: f32,
     pub population_size: usize,
+    pub episodes: Vec<Episode>,
 }